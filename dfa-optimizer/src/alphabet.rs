@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+
+/// The symbols of a table and the column each one uses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Alphabet {
+    symbols: Vec<char>,
+    columns: BTreeMap<char, usize>,
+}
+
+impl Alphabet {
+    /// Symbols get columns in order. Repeated symbols keep their first
+    /// column.
+    pub fn new<I: IntoIterator<Item = char>>(symbols: I) -> Self {
+        let mut alphabet = Self::default();
+        for c in symbols {
+            if !alphabet.columns.contains_key(&c) {
+                alphabet.columns.insert(c, alphabet.symbols.len());
+                alphabet.symbols.push(c);
+            }
+        }
+        alphabet
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[char] {
+        &self.symbols
+    }
+
+    pub fn column(&self, symbol: char) -> Option<usize> {
+        self.columns.get(&symbol).copied()
+    }
+
+    pub fn symbol(&self, column: usize) -> Option<char> {
+        self.symbols.get(column).copied()
+    }
+
+    /// Symbol to column mapping, as the binaries used to build by hand.
+    pub fn mapping(&self) -> &BTreeMap<char, usize> {
+        &self.columns
+    }
+}
+
+impl FromIterator<char> for Alphabet {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        Alphabet::new(iter)
+    }
+}
+
+impl fmt::Display for Alphabet {
+    /// The symbols separated by spaces, like in a .nfa header.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbols: Vec<String> = self.symbols.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", symbols.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_repeated_symbol_keeps_first_column() {
+        let alphabet = Alphabet::new("ababc".chars());
+        assert_eq!(alphabet.symbols(), &['a', 'b', 'c']);
+        assert_eq!(alphabet.len(), 3);
+        assert_eq!(alphabet.column('a'), Some(0));
+        assert_eq!(alphabet.column('b'), Some(1));
+        assert_eq!(alphabet.column('c'), Some(2));
+        assert_eq!(alphabet.to_string(), "a b c");
+    }
+
+    #[test]
+    fn test_lookups() {
+        let alphabet: Alphabet = "αβ🦀".chars().collect();
+        for (column, symbol) in alphabet.symbols().iter().enumerate() {
+            assert_eq!(alphabet.column(*symbol), Some(column));
+            assert_eq!(alphabet.symbol(column), Some(*symbol));
+            assert_eq!(alphabet.mapping()[symbol], column);
+        }
+        assert_eq!(alphabet.column('a'), None);
+        assert_eq!(alphabet.symbol(3), None);
+
+        let empty = Alphabet::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.symbol(0), None);
+        assert_eq!(empty.to_string(), "");
+    }
+}
@@ -1,12 +1,10 @@
 use log::*;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-use dfa_optimizer::{Row, Table};
-
-use std::collections::BTreeMap;
+use dfa_optimizer::{Format, Minimizer, Table};
 
 /// dfa reads in a formatted DFA file and spits
 /// out an optimized form of given DFA.
@@ -21,12 +19,21 @@ struct Args {
     /// Path to output the optimized DFA
     #[structopt(short, long)]
     out: PathBuf,
-    /// The alphabet of our DFA
+    /// The alphabet of our DFA, used when the file has no header line
     #[structopt(short, long)]
     alphabet: Vec<char>,
     /// Tokens to match the DFA against
     #[structopt(short, long)]
     tokens: Vec<String>,
+    /// Use the original pass-based optimizer instead of Hopcroft's algorithm
+    #[structopt(long)]
+    legacy: bool,
+    /// Output format: text or json (json needs the `serde` feature)
+    #[structopt(long, default_value = "text")]
+    format: Format,
+    /// Write missing transitions as a sink row instead of `E`
+    #[structopt(long)]
+    complete: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -34,51 +41,55 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::from_args();
 
     let file = File::open(args.file)?;
-    let reader = BufReader::new(file);
-
-    let rows: Vec<Row> = reader
-        .lines()
-        .flatten()
-        .map(|r| r.parse().unwrap())
-        .collect();
-
-    let mut table = Table::from(rows);
+    let mut table = match Table::from_reader(file) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Files without a header take their alphabet from the arguments.
+    if table.alphabet().is_empty() && !args.alphabet.is_empty() {
+        if let Err(e) = table.set_alphabet(args.alphabet.iter().copied().collect()) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
 
     if args.verbose {
         debug!("Input DFA:");
         print!("{}", table);
     }
 
-    table.optimize();
+    if args.legacy {
+        table.optimize_with(Minimizer::Legacy);
+    } else {
+        table.optimize();
+    }
 
     if args.verbose {
         debug!("\nOptimal DFA:");
         print!("{}", table);
     }
 
-    // args.alphabet.sort();
-    let mut mapping = BTreeMap::new();
-
-    for (i, c) in args.alphabet.iter().enumerate() {
-        mapping.insert(c.clone(), i);
-    }
-
     if args.verbose {
-        debug!("Alphabet: {:?}", args.alphabet);
+        debug!("Alphabet: {}", table.alphabet());
         debug!("Tokens: {:?}", args.tokens);
-        debug!("Mapping: {:#?}", mapping);
     }
 
     for token in args.tokens {
-        debug!("{}: {:?}", token, table.does_match(&token, &mapping));
+        debug!("{}: {:?}", token, table.does_match(&token));
+    }
+
+    if args.complete {
+        table.complete();
     }
 
     let new_file = File::create(args.out)?;
     let mut writer = BufWriter::new(new_file);
 
-    for row in table.rows() {
-        writeln!(writer, "{}", row)?;
-    }
+    table.write_as(args.format, &mut writer)?;
 
     writer.flush()?;
 
@@ -0,0 +1,130 @@
+//! Graphviz DOT output.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::table::Table;
+
+/// Shortest run of consecutive symbols that `edge_label` writes as a range.
+const MIN_RANGE: usize = 3;
+
+/// The label of an edge taken on all of `symbols`. Runs of consecutive
+/// characters are merged into ranges like `0-9`, and the label is escaped
+/// for use inside a quoted DOT string.
+pub fn edge_label(symbols: &[char]) -> String {
+    let mut symbols = symbols.to_vec();
+    symbols.sort_unstable();
+    symbols.dedup();
+
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < symbols.len() {
+        let mut end = i;
+        while end + 1 < symbols.len() && symbols[end + 1] as u32 == symbols[end] as u32 + 1 {
+            end += 1;
+        }
+
+        if end + 1 - i >= MIN_RANGE {
+            parts.push(format!("{}-{}", symbols[i], symbols[end]));
+        } else {
+            parts.extend(symbols[i..=end].iter().map(|c| c.to_string()));
+        }
+        i = end + 1;
+    }
+
+    escape(&parts.join(","))
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes the lines shared by every graph: left to right layout, an arrow
+/// into state 0 and double circles for accepting states.
+pub fn write_header<I: IntoIterator<Item = usize>>(
+    out: &mut String,
+    name: &str,
+    accepting: I,
+) -> std::fmt::Result {
+    writeln!(out, "digraph {} {{", name)?;
+    writeln!(out, "    rankdir=LR;")?;
+    writeln!(out, "    node [shape=circle];")?;
+    writeln!(out, "    start [shape=point];")?;
+    writeln!(out, "    start -> 0;")?;
+    for state in accepting {
+        writeln!(out, "    {} [shape=doublecircle];", state)?;
+    }
+
+    Ok(())
+}
+
+impl Table {
+    /// The table as a DOT digraph. Edges between the same two rows are
+    /// merged into one, labelled with all of their symbols. Tables without
+    /// an alphabet are labelled with column numbers.
+    pub fn to_dot(&self) -> String {
+        let mut edges: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        for (from, row) in self.rows().iter().enumerate() {
+            for (column, to) in row.transitions().iter().enumerate() {
+                if let Some(to) = to {
+                    edges.entry((from, *to)).or_default().push(column);
+                }
+            }
+        }
+
+        let accepting = (0..self.rows().len()).filter(|s| self[*s].is_accepting());
+        let mut out = String::new();
+        // Writing to a String cannot fail.
+        write_header(&mut out, "dfa", accepting).unwrap();
+        for ((from, to), columns) in edges {
+            let label = if self.alphabet().is_empty() {
+                let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+                columns.join(",")
+            } else {
+                let symbols: Vec<char> = columns
+                    .iter()
+                    .filter_map(|c| self.alphabet().symbol(*c))
+                    .collect();
+                edge_label(&symbols)
+            };
+            writeln!(out, "    {} -> {} [label=\"{}\"];", from, to, label).unwrap();
+        }
+        out.push_str("}\n");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edge_label() {
+        let digits: Vec<char> = "9876543210".chars().collect();
+        assert_eq!(edge_label(&digits), "0-9");
+        assert_eq!(edge_label(&['b', 'a']), "a,b");
+        assert_eq!(edge_label(&['a', 'b', 'c', 'x', '"']), "\\\",a-c,x");
+        assert_eq!(edge_label(&['\\']), "\\\\");
+    }
+
+    #[test]
+    fn test_table_to_dot() {
+        let table =
+            Table::from_reader("DFA 1 2 0 1 2 .\n- 0 1 1 1 E\n+ 1 1 1 1 0\n".as_bytes()).unwrap();
+        assert_eq!(
+            table.to_dot(),
+            "digraph dfa {
+    rankdir=LR;
+    node [shape=circle];
+    start [shape=point];
+    start -> 0;
+    1 [shape=doublecircle];
+    0 -> 1 [label=\"0-2\"];
+    1 -> 0 [label=\".\"];
+    1 -> 1 [label=\"0-2\"];
+}
+"
+        );
+    }
+}
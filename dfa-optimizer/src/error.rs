@@ -0,0 +1,185 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors from parsing a single `+ id t1 t2 ... [@token]` row. Columns are
+/// 1-based and count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowParseError {
+    /// The row has no id after its marker.
+    ShortRow { fields: usize },
+    /// The row does not start with `+` or `-`.
+    BadMarker { column: usize, token: String },
+    /// The row id is not a number.
+    NonNumericId { column: usize, token: String },
+    /// A transition is neither a row id nor `E`.
+    BadTransition { column: usize, token: String },
+    /// The `@` field after the transitions is not a token id.
+    BadToken { column: usize, token: String },
+}
+
+impl fmt::Display for RowParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RowParseError::ShortRow { fields } => write!(
+                f,
+                "row has {} field(s), expected a marker and an id",
+                fields
+            ),
+            RowParseError::BadMarker { column, token } => write!(
+                f,
+                "column {}: expected `+` or `-`, found `{}`",
+                column, token
+            ),
+            RowParseError::NonNumericId { column, token } => {
+                write!(f, "column {}: row id `{}` is not a number", column, token)
+            }
+            RowParseError::BadTransition { column, token } => write!(
+                f,
+                "column {}: transition `{}` is neither a row id nor `E`",
+                column, token
+            ),
+            RowParseError::BadToken { column, token } => {
+                write!(f, "column {}: `{}` is not a token id", column, token)
+            }
+        }
+    }
+}
+
+impl Error for RowParseError {}
+
+/// From `Table::set_alphabet` and the product operations like
+/// `Table::union`: the rows and the alphabet disagree on the number of
+/// columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlphabetWidthError {
+    pub transitions: usize,
+    pub symbols: usize,
+}
+
+impl fmt::Display for AlphabetWidthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "rows have {} transition(s) but the alphabet has {} symbols",
+            self.transitions, self.symbols
+        )
+    }
+}
+
+impl Error for AlphabetWidthError {}
+
+/// Errors from `Table::from_reader`. Lines are 1-based.
+#[derive(Debug)]
+pub enum DfaParseError {
+    /// The input could not be read.
+    Io(io::Error),
+    /// The header is malformed: a missing or non-numeric count, or a
+    /// symbol that is not a single character or escape, or is repeated.
+    BadHeader { line: usize, token: String },
+    /// The header names a format version this crate cannot read.
+    UnsupportedVersion { line: usize, version: String },
+    /// A row could not be parsed.
+    Row { line: usize, source: RowParseError },
+    /// A row has a different number of transitions than the alphabet has
+    /// symbols, or than the first row when there is no header.
+    WrongWidth {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// Row ids must count up from 0 in file order.
+    RowOutOfOrder {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A transition points past the last row.
+    TargetOutOfRange {
+        line: usize,
+        target: usize,
+        num_rows: usize,
+    },
+    /// The header declares a different number of rows than follow it.
+    StateCountMismatch { declared: usize, found: usize },
+}
+
+impl DfaParseError {
+    /// The line of the error, if it points at one.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            DfaParseError::Io(_) | DfaParseError::StateCountMismatch { .. } => None,
+            DfaParseError::BadHeader { line, .. }
+            | DfaParseError::UnsupportedVersion { line, .. }
+            | DfaParseError::Row { line, .. }
+            | DfaParseError::WrongWidth { line, .. }
+            | DfaParseError::RowOutOfOrder { line, .. }
+            | DfaParseError::TargetOutOfRange { line, .. } => Some(*line),
+        }
+    }
+}
+
+impl fmt::Display for DfaParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DfaParseError::Io(source) => write!(f, "{}", source),
+            DfaParseError::BadHeader { line, token } => {
+                write!(f, "line {}: bad header field `{}`", line, token)
+            }
+            DfaParseError::UnsupportedVersion { line, version } => write!(
+                f,
+                "line {}: unsupported .dfa format version `{}`",
+                line, version
+            ),
+            DfaParseError::Row { line, source } => write!(f, "line {}: {}", line, source),
+            DfaParseError::WrongWidth {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: row has {} transition(s), expected {}",
+                line, found, expected
+            ),
+            DfaParseError::RowOutOfOrder {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected row {}, found row {}",
+                line, expected, found
+            ),
+            DfaParseError::TargetOutOfRange {
+                line,
+                target,
+                num_rows,
+            } => write!(
+                f,
+                "line {}: transition to row {} is out of range, the table has {} rows",
+                line, target, num_rows
+            ),
+            DfaParseError::StateCountMismatch { declared, found } => write!(
+                f,
+                "header declares {} rows but {} follow it",
+                declared, found
+            ),
+        }
+    }
+}
+
+impl Error for DfaParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DfaParseError::Io(source) => Some(source),
+            DfaParseError::Row { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DfaParseError {
+    fn from(e: io::Error) -> Self {
+        DfaParseError::Io(e)
+    }
+}
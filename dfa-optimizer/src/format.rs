@@ -0,0 +1,407 @@
+//! Reading and writing .dfa files.
+//!
+//! A file starts with a header `DFA <version> <rows> <symbols...>`,
+//! followed by one `+ id t1 t2 ...` line per row, where `E` means no
+//! transition. Transitions are listed in alphabet order. Since version 2 a
+//! row of a scanner table ends with `@<token>`, the id of the token it
+//! accepts. Since version 3 the header writes the symbols space, tab,
+//! newline and `\` as `\s`, `\t`, `\n` and `\\`. Version 1 and 2 files,
+//! files without a header, and files with an unversioned
+//! `<rows> <symbols...>` header are still read. When there are no symbols
+//! the rows only have to agree with each other on the width.
+
+use log::*;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+
+use crate::alphabet::Alphabet;
+use crate::error::DfaParseError;
+use crate::row::Row;
+use crate::table::Table;
+
+/// The first field of a versioned header.
+pub const FORMAT_MAGIC: &str = "DFA";
+/// The version `Table::write_to` writes, and the newest one
+/// `Table::from_reader` reads.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Output formats for the binaries, see `Table::write_as`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The .dfa text format.
+    #[default]
+    Text,
+    /// The JSON schema documented in the `json` module.
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, String> {
+        match input {
+            "text" => Ok(Format::Text),
+            #[cfg(feature = "serde")]
+            "json" => Ok(Format::Json),
+            #[cfg(not(feature = "serde"))]
+            "json" => Err("json output needs the `serde` feature".to_owned()),
+            _ => Err(format!("unknown format `{}`, expected text or json", input)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Text => write!(f, "text"),
+            #[cfg(feature = "serde")]
+            Format::Json => write!(f, "json"),
+        }
+    }
+}
+
+struct Header {
+    declared: usize,
+    alphabet: Alphabet,
+}
+
+impl Table {
+    /// The header line `Table::write_to` writes above the rows.
+    pub fn header(&self) -> String {
+        let mut header = format!("{} {} {}", FORMAT_MAGIC, FORMAT_VERSION, self.rows().len());
+        for symbol in self.alphabet().symbols() {
+            header.push(' ');
+            match symbol {
+                ' ' => header.push_str("\\s"),
+                '\t' => header.push_str("\\t"),
+                '\n' => header.push_str("\\n"),
+                '\\' => header.push_str("\\\\"),
+                c => header.push(*c),
+            }
+        }
+        header
+    }
+
+    /// Writes the header and rows. `Table::from_reader` reads the output
+    /// back into the same rows, tokens and alphabet.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", self.header())?;
+        for row in self.rows() {
+            writeln!(writer, "{}", row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the table in the given format.
+    pub fn write_as<W: Write>(&self, format: Format, writer: W) -> io::Result<()> {
+        match format {
+            Format::Text => self.write_to(writer),
+            #[cfg(feature = "serde")]
+            Format::Json => {
+                let mut writer = writer;
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)
+            }
+        }
+    }
+
+    /// Reads a table, checking that row ids count up from 0, that every
+    /// row has one transition per symbol and that transitions stay inside
+    /// the table. Blank lines are skipped.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Table, DfaParseError> {
+        let mut header = None;
+        let mut rows: Vec<(usize, Row)> = Vec::new();
+
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line_no = i + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if rows.is_empty() && header.is_none() {
+                header = parse_header(line_no, &line)?;
+                if header.is_some() {
+                    continue;
+                }
+            }
+
+            let row: Row = line.parse().map_err(|source| DfaParseError::Row {
+                line: line_no,
+                source,
+            })?;
+
+            if row.id != rows.len() {
+                return Err(DfaParseError::RowOutOfOrder {
+                    line: line_no,
+                    expected: rows.len(),
+                    found: row.id,
+                });
+            }
+
+            // A header without symbols comes from a table that never had an
+            // alphabet, so the first row decides the width as well.
+            let expected = match (&header, rows.first()) {
+                (Some(header), _) if !header.alphabet.is_empty() => Some(header.alphabet.len()),
+                (_, Some((_, first))) => Some(first.transitions().len()),
+                (_, None) => None,
+            };
+            if let Some(expected) = expected {
+                if row.transitions().len() != expected {
+                    return Err(DfaParseError::WrongWidth {
+                        line: line_no,
+                        expected,
+                        found: row.transitions().len(),
+                    });
+                }
+            }
+
+            rows.push((line_no, row));
+        }
+
+        if let Some(header) = &header {
+            if header.declared != rows.len() {
+                return Err(DfaParseError::StateCountMismatch {
+                    declared: header.declared,
+                    found: rows.len(),
+                });
+            }
+        }
+
+        for (line, row) in &rows {
+            if let Some(target) = row
+                .transitions()
+                .iter()
+                .flatten()
+                .find(|t| **t >= rows.len())
+            {
+                return Err(DfaParseError::TargetOutOfRange {
+                    line: *line,
+                    target: *target,
+                    num_rows: rows.len(),
+                });
+            }
+        }
+
+        debug!("read {} rows", rows.len());
+        let table = Table::from(rows.into_iter().map(|(_, row)| row).collect::<Vec<_>>());
+        Ok(match header {
+            Some(header) => table.with_alphabet(header.alphabet),
+            None => table,
+        })
+    }
+}
+
+/// Parses the first line as a header, or returns `None` if it is a row.
+fn parse_header(line: usize, input: &str) -> Result<Option<Header>, DfaParseError> {
+    let mut tokens = input.split_whitespace();
+    let bad = |token: &str| DfaParseError::BadHeader {
+        line,
+        token: token.to_owned(),
+    };
+
+    let (count, escaped) = match tokens.next() {
+        Some("+") | Some("-") => return Ok(None),
+        Some(FORMAT_MAGIC) => {
+            let version = tokens.next().ok_or_else(|| bad(""))?;
+            let version = match version.parse::<u32>() {
+                Ok(version @ 1..=FORMAT_VERSION) => version,
+                _ => {
+                    return Err(DfaParseError::UnsupportedVersion {
+                        line,
+                        version: version.to_owned(),
+                    })
+                }
+            };
+            (tokens.next().ok_or_else(|| bad(""))?, version >= 3)
+        }
+        // An unversioned `<rows> <symbols...>` header.
+        Some(count) => (count, false),
+        None => return Err(bad("")),
+    };
+    let declared = count.parse().map_err(|_| bad(count))?;
+
+    let mut symbols = Vec::new();
+    for token in tokens {
+        let mut chars = token.chars();
+        let symbol = match (chars.next(), chars.next(), chars.next()) {
+            (Some('\\'), Some(escape), None) if escaped => match escape {
+                's' => Some(' '),
+                't' => Some('\t'),
+                'n' => Some('\n'),
+                '\\' => Some('\\'),
+                _ => None,
+            },
+            (Some('\\'), None, _) if escaped => None,
+            (Some(c), None, _) => Some(c),
+            _ => None,
+        };
+        match symbol {
+            Some(c) if !symbols.contains(&c) => symbols.push(c),
+            _ => return Err(bad(token)),
+        }
+    }
+
+    Ok(Some(Header {
+        declared,
+        alphabet: Alphabet::new(symbols),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read(input: &str) -> Result<Table, DfaParseError> {
+        Table::from_reader(input.as_bytes())
+    }
+
+    fn write(table: &Table) -> String {
+        let mut out = Vec::new();
+        table.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "DFA 3 3 a b λ\n- 0 1 E E\n+ 1 1 2 E\n+ 2 E E 0\n";
+        let table = read(text).unwrap();
+        assert_eq!(table.alphabet().symbols(), &['a', 'b', 'λ']);
+        assert_eq!(write(&table), text);
+
+        let again = read(&write(&table)).unwrap();
+        assert_eq!(again.rows(), table.rows());
+        assert_eq!(again.alphabet(), table.alphabet());
+    }
+
+    #[test]
+    fn test_round_trip_whitespace_symbols() {
+        let text = "DFA 3 2 \\s \\t \\n \\\\ s\n- 0 1 1 1 1 E\n+ 1 E E E E E\n";
+        let table = read(text).unwrap();
+        assert_eq!(table.alphabet().symbols(), &[' ', '\t', '\n', '\\', 's']);
+        assert_eq!(write(&table), text);
+        assert!(table.does_match(" ").is_accepted());
+        assert!(table.does_match("\\").is_accepted());
+        assert!(!table.does_match("s").is_accepted());
+    }
+
+    #[test]
+    fn test_round_trip_empty_alphabet() {
+        let table = Table::empty_language(Alphabet::default());
+        let text = write(&table);
+        assert_eq!(text, "DFA 3 1\n- 0\n");
+        assert_eq!(write(&read(&text).unwrap()), text);
+
+        let text = "DFA 3 2\n- 0 1 E\n+ 1 E 0\n";
+        assert_eq!(write(&read(text).unwrap()), text);
+    }
+
+    #[test]
+    fn test_round_trip_tokens() {
+        let text = "DFA 3 3 a b\n- 0 1 2\n+ 1 1 1 @1\n+ 2 2 2 @0\n";
+        let mut table = read(text).unwrap();
+        assert_eq!(table[1].token(), Some(1));
+        assert_eq!(table[2].token(), Some(0));
+        assert_eq!(write(&table), text);
+
+        // Minimizing keeps rows of different tokens apart.
+        table.optimize();
+        let again = read(&write(&table)).unwrap();
+        assert_eq!(again.rows(), table.rows());
+        assert_eq!(again.rows().len(), 3);
+    }
+
+    #[test]
+    fn test_old_formats() {
+        let table = read("DFA 1 2 a\n- 0 1\n+ 1 E\n").unwrap();
+        assert_eq!(write(&table), "DFA 3 2 a\n- 0 1\n+ 1 E\n");
+
+        // Before version 3 symbols were not escaped.
+        let table = read("DFA 2 1 \\ s\n- 0 E E\n").unwrap();
+        assert_eq!(table.alphabet().symbols(), &['\\', 's']);
+        assert_eq!(write(&table), "DFA 3 1 \\\\ s\n- 0 E E\n");
+
+        let table = read("- 0 1\r\n+ 1 E\r\n\n").unwrap();
+        assert_eq!(table.rows().len(), 2);
+        assert!(table.alphabet().is_empty());
+
+        let table = read("2 x\n- 0 1\n+ 1 E\n").unwrap();
+        assert_eq!(table.alphabet().symbols(), &['x']);
+        assert!(table.does_match("x").is_accepted());
+    }
+
+    #[test]
+    fn test_sample_files_parse() {
+        for name in &["cblock", "class", "dead_loop", "multiline_comment"] {
+            for suffix in &["", "_opt"] {
+                let path = format!("{}/{}{}.dfa", env!("CARGO_MANIFEST_DIR"), name, suffix);
+                let file = std::fs::File::open(&path).unwrap();
+                assert!(Table::from_reader(file).is_ok(), "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            read("DFA 4 1 a\n- 0 E\n"),
+            Err(DfaParseError::UnsupportedVersion { line: 1, .. })
+        ));
+        assert!(matches!(
+            read("DFA 1 1 ab\n- 0 E\n"),
+            Err(DfaParseError::BadHeader { line: 1, ref token }) if token == "ab"
+        ));
+        assert!(matches!(
+            read("DFA 1 1 a a\n- 0 E E\n"),
+            Err(DfaParseError::BadHeader { line: 1, .. })
+        ));
+        for token in &["\\", "\\x", "\\ss"] {
+            assert!(matches!(
+                read(&format!("DFA 3 1 {}\n- 0 E\n", token)),
+                Err(DfaParseError::BadHeader { line: 1, token: ref t }) if t == token
+            ));
+        }
+        assert!(matches!(
+            read("DFA 1 1 a\n\n- 0 x\n"),
+            Err(DfaParseError::Row { line: 3, .. })
+        ));
+        assert!(matches!(
+            read("DFA 1 2 a\n- 0 1\n+ 2 E\n"),
+            Err(DfaParseError::RowOutOfOrder {
+                line: 3,
+                expected: 1,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            read("DFA 1 1 a b\n- 0 E\n"),
+            Err(DfaParseError::WrongWidth {
+                line: 2,
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            read("- 0 1 E\n+ 1 E\n"),
+            Err(DfaParseError::WrongWidth { line: 2, .. })
+        ));
+        assert!(matches!(
+            read("DFA 1 1 a\n- 0 3\n"),
+            Err(DfaParseError::TargetOutOfRange {
+                line: 2,
+                target: 3,
+                num_rows: 1
+            })
+        ));
+        assert!(matches!(
+            read("DFA 1 3 a\n- 0 E\n"),
+            Err(DfaParseError::StateCountMismatch {
+                declared: 3,
+                found: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! JSON form of `Table`, behind the `serde` feature.
+//!
+//! ```json
+//! {
+//!   "alphabet": ["a", "b"],
+//!   "states": 2,
+//!   "accepting": [1],
+//!   "transitions": [[1, null], [null, 0]],
+//!   "tokens": {"1": 0}
+//! }
+//! ```
+//!
+//! State 0 is the start state. `transitions[s][c]` is where state `s` goes
+//! on `alphabet[c]`, or `null`. `tokens` maps accepting states of scanner
+//! tables to their token id and is left out when empty.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::alphabet::Alphabet;
+use crate::row::Row;
+use crate::table::Table;
+
+#[derive(Serialize, Deserialize)]
+struct TableJson {
+    alphabet: Vec<char>,
+    states: usize,
+    accepting: Vec<usize>,
+    transitions: Vec<Vec<Option<usize>>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tokens: BTreeMap<usize, usize>,
+}
+
+impl From<&Table> for TableJson {
+    fn from(table: &Table) -> Self {
+        let rows = table.rows();
+        Self {
+            alphabet: table.alphabet().symbols().to_vec(),
+            states: rows.len(),
+            accepting: (0..rows.len())
+                .filter(|s| rows[*s].is_accepting())
+                .collect(),
+            transitions: rows.iter().map(|row| row.transitions().to_vec()).collect(),
+            tokens: (0..rows.len())
+                .filter_map(|s| rows[s].token().map(|t| (s, t)))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<TableJson> for Table {
+    type Error = String;
+
+    fn try_from(json: TableJson) -> Result<Self, String> {
+        let alphabet = Alphabet::new(json.alphabet.iter().copied());
+        if alphabet.len() != json.alphabet.len() {
+            return Err("alphabet has a repeated symbol".to_owned());
+        }
+        if json.transitions.len() != json.states {
+            return Err(format!(
+                "{} states declared but {} rows of transitions given",
+                json.states,
+                json.transitions.len()
+            ));
+        }
+
+        let states = json.states;
+        let check_state = |state: usize| {
+            if state < states {
+                Ok(state)
+            } else {
+                Err(format!(
+                    "state {} is out of range, there are {} states",
+                    state, states
+                ))
+            }
+        };
+
+        let mut rows = Vec::with_capacity(json.states);
+        for (id, transitions) in json.transitions.into_iter().enumerate() {
+            if transitions.len() != alphabet.len() {
+                return Err(format!(
+                    "state {} has {} transitions, expected {}",
+                    id,
+                    transitions.len(),
+                    alphabet.len()
+                ));
+            }
+            for target in transitions.iter().flatten() {
+                check_state(*target)?;
+            }
+            rows.push(Row::new(false, id, transitions));
+        }
+
+        for state in json.accepting {
+            rows[check_state(state)?].set_accepting(true);
+        }
+        for (state, token) in json.tokens {
+            rows[check_state(state)?].set_token(Some(token));
+        }
+
+        Ok(Table::from(rows).with_alphabet(alphabet))
+    }
+}
+
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TableJson::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Table {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = TableJson::deserialize(deserializer)?;
+        Table::try_from(json).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_schema() {
+        let mut table = Table::from_reader("DFA 1 2 a b\n- 0 1 E\n+ 1 E 0\n".as_bytes()).unwrap();
+        table[1].set_token(Some(0));
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(
+            json,
+            r#"{"alphabet":["a","b"],"states":2,"accepting":[1],"transitions":[[1,null],[null,0]],"tokens":{"1":0}}"#
+        );
+
+        let back: Table = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.rows(), table.rows());
+        assert_eq!(back.alphabet(), table.alphabet());
+    }
+
+    #[test]
+    fn test_rejects_bad_tables() {
+        for json in &[
+            r#"{"alphabet":["a"],"states":2,"accepting":[],"transitions":[[1]]}"#,
+            r#"{"alphabet":["a"],"states":1,"accepting":[],"transitions":[[1]]}"#,
+            r#"{"alphabet":["a"],"states":1,"accepting":[3],"transitions":[[0]]}"#,
+            r#"{"alphabet":["a","b"],"states":1,"accepting":[],"transitions":[[0]]}"#,
+            r#"{"alphabet":["a","a"],"states":1,"accepting":[],"transitions":[[0,0]]}"#,
+        ] {
+            assert!(serde_json::from_str::<Table>(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_row() {
+        let row: Row = "+ 2 E 1".parse().unwrap();
+        let json = serde_json::to_string(&row).unwrap();
+        assert_eq!(json, r#"{"accepting":true,"id":2,"transitions":[null,1]}"#);
+        assert_eq!(serde_json::from_str::<Row>(&json).unwrap(), row);
+    }
+}
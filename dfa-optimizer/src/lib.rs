@@ -1,10 +1,23 @@
-#![feature(map_first_last)]
-
+pub mod alphabet;
+pub mod dot;
+pub mod error;
+pub mod format;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod product;
 pub mod row;
 pub mod table;
+#[cfg(any(test, feature = "test-util"))]
+#[doc(hidden)]
+pub mod test_util;
+pub mod tokenize;
 
+pub use alphabet::Alphabet;
+pub use error::{AlphabetWidthError, DfaParseError, RowParseError};
+pub use format::{Format, FORMAT_MAGIC, FORMAT_VERSION};
 pub use row::Row;
-pub use table::Table;
+pub use table::{MatchOutcome, Merges, Minimizer, Position, Table};
+pub use tokenize::{Token, Tokens};
 
 #[cfg(test)]
 mod tests {
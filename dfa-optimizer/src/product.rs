@@ -0,0 +1,267 @@
+//! Combining the languages of two tables with the product construction.
+//! The operations fail when only one table has an alphabet and the rows of
+//! the other do not have a column for each of its symbols.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::alphabet::Alphabet;
+use crate::error::AlphabetWidthError;
+use crate::row::Row;
+use crate::table::Table;
+
+/// A row of the left table and a row of the right one. `None` is the
+/// implicit non-accepting sink that missing transitions lead to.
+type Pair = (Option<usize>, Option<usize>);
+/// The column of a symbol in each table, if the table has it.
+type Columns = (Option<usize>, Option<usize>);
+
+impl Table {
+    /// The strings both tables match.
+    pub fn intersect(&self, other: &Table) -> Result<Table, AlphabetWidthError> {
+        self.product(other, |a, b| a && b)
+    }
+
+    /// The strings either table matches.
+    pub fn union(&self, other: &Table) -> Result<Table, AlphabetWidthError> {
+        self.product(other, |a, b| a || b)
+    }
+
+    /// The strings this table matches and `other` does not.
+    pub fn difference(&self, other: &Table) -> Result<Table, AlphabetWidthError> {
+        self.product(other, |a, b| a && !b)
+    }
+
+    /// The strings exactly one of the tables matches.
+    pub fn symmetric_difference(&self, other: &Table) -> Result<Table, AlphabetWidthError> {
+        self.product(other, |a, b| a != b)
+    }
+
+    /// Runs both tables side by side; a row of the result accepts when
+    /// `accept` holds for the two rows it stands for. When both tables have
+    /// an alphabet the result's alphabet is this table's symbols followed
+    /// by the new symbols of `other`, and a table goes to the sink on
+    /// symbols it does not know. Otherwise columns are paired by position
+    /// and the result keeps the alphabet of either side.
+    /// Rows are numbered in breadth-first order from row 0 and tokens are
+    /// not kept; pass the result to `optimize` to minimize it.
+    fn product<F: Fn(bool, bool) -> bool>(
+        &self,
+        other: &Table,
+        accept: F,
+    ) -> Result<Table, AlphabetWidthError> {
+        let (alphabet, columns) = align(self, other)?;
+
+        // A side whose sink can never lead to acceptance, like either side
+        // of an intersection, ends the pair once it reaches the sink.
+        let dead_left = !accept(false, false) && !accept(false, true);
+        let dead_right = !accept(false, false) && !accept(true, false);
+        let is_dead = |(left, right): Pair| {
+            (left.is_none() && right.is_none())
+                || (left.is_none() && dead_left)
+                || (right.is_none() && dead_right)
+        };
+
+        let start = (
+            Some(0).filter(|_| !self.rows().is_empty()),
+            Some(0).filter(|_| !other.rows().is_empty()),
+        );
+        if is_dead(start) {
+            return Ok(Table::empty_language(alphabet));
+        }
+
+        let mut ids: HashMap<Pair, usize> = HashMap::new();
+        let mut pairs = VecDeque::new();
+        let mut rows = Vec::new();
+        ids.insert(start, 0);
+        pairs.push_back(start);
+
+        while let Some(pair) = pairs.pop_front() {
+            let (left, right) = pair;
+            let accepting = accept(
+                left.is_some_and(|row| self[row].is_accepting()),
+                right.is_some_and(|row| other[row].is_accepting()),
+            );
+
+            let mut transitions = Vec::with_capacity(columns.len());
+            for (left_column, right_column) in &columns {
+                let next = (
+                    step(self, left, *left_column),
+                    step(other, right, *right_column),
+                );
+                if is_dead(next) {
+                    transitions.push(None);
+                    continue;
+                }
+
+                let next_id = ids.len();
+                let id = *ids.entry(next).or_insert_with(|| {
+                    pairs.push_back(next);
+                    next_id
+                });
+                transitions.push(Some(id));
+            }
+
+            rows.push(Row::new(accepting, rows.len(), transitions));
+        }
+
+        Ok(Table::from(rows).with_alphabet(alphabet))
+    }
+}
+
+/// The result's alphabet and the table columns behind each of its columns.
+fn align(left: &Table, right: &Table) -> Result<(Alphabet, Vec<Columns>), AlphabetWidthError> {
+    if left.alphabet().is_empty() || right.alphabet().is_empty() {
+        // A table without rows or alphabet fits any width.
+        let width = |table: &Table| match table.alphabet().len() {
+            0 => table.rows().first().map(|row| row.transitions().len()),
+            symbols => Some(symbols),
+        };
+        let (left_width, right_width) = (width(left), width(right));
+        let (alphabet, bare_width) = if left.alphabet().is_empty() {
+            (right.alphabet(), left_width)
+        } else {
+            (left.alphabet(), right_width)
+        };
+        if let (false, Some(transitions)) = (alphabet.is_empty(), bare_width) {
+            if transitions != alphabet.len() {
+                return Err(AlphabetWidthError {
+                    transitions,
+                    symbols: alphabet.len(),
+                });
+            }
+        }
+
+        let (left_width, right_width) = (left_width.unwrap_or(0), right_width.unwrap_or(0));
+        let columns = (0..left_width.max(right_width))
+            .map(|c| {
+                (
+                    Some(c).filter(|c| *c < left_width),
+                    Some(c).filter(|c| *c < right_width),
+                )
+            })
+            .collect();
+        return Ok((alphabet.clone(), columns));
+    }
+
+    let alphabet: Alphabet = left
+        .alphabet()
+        .symbols()
+        .iter()
+        .chain(right.alphabet().symbols())
+        .copied()
+        .collect();
+    let columns = alphabet
+        .symbols()
+        .iter()
+        .map(|c| (left.alphabet().column(*c), right.alphabet().column(*c)))
+        .collect();
+    Ok((alphabet, columns))
+}
+
+fn step(table: &Table, row: Option<usize>, column: Option<usize>) -> Option<usize> {
+    table.rows().get(row?)?.transition(column?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::table::MatchOutcome;
+    use crate::test_util::strings;
+
+    fn read(input: &str) -> Table {
+        Table::from_reader(input.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_identifiers_that_are_not_keywords() {
+        let identifiers = read("DFA 1 2 i f x\n- 0 1 1 1\n+ 1 1 1 1\n");
+        let keyword = read("DFA 1 3 i f\n- 0 1 E\n- 1 E 2\n+ 2 E E\n");
+        let mut names = identifiers.difference(&keyword).unwrap();
+        assert_eq!(names.alphabet().symbols(), &['i', 'f', 'x']);
+
+        for input in &["i", "f", "x", "iff", "fi", "ifx"] {
+            assert!(names.does_match(input).is_accepted(), "{}", input);
+        }
+        assert!(!names.does_match("if").is_accepted());
+        assert!(!names.does_match("").is_accepted());
+
+        names.optimize();
+        assert!(!names.does_match("if").is_accepted());
+        assert!(names.does_match("xif").is_accepted());
+    }
+
+    #[test]
+    fn test_operations_agree_with_both_tables() {
+        // Even number of a's, over a and b.
+        let even = read("DFA 1 2 a b\n+ 0 1 0\n- 1 0 1\n");
+        // Ends in c, over b and c.
+        let ends_c = read("DFA 1 2 b c\n- 0 0 1\n+ 1 0 1\n");
+
+        let check = |mut table: Table, expected: fn(bool, bool) -> bool| {
+            assert_eq!(table.alphabet().symbols(), &['a', 'b', 'c']);
+            for pass in 0..2 {
+                for input in strings(&['a', 'b', 'c'], 5) {
+                    let want = expected(
+                        even.does_match(&input).is_accepted(),
+                        ends_c.does_match(&input).is_accepted(),
+                    );
+                    assert_eq!(table.does_match(&input).is_accepted(), want, "{:?}", input);
+                }
+                if pass == 0 {
+                    table.optimize();
+                }
+            }
+        };
+
+        check(even.intersect(&ends_c).unwrap(), |a, b| a && b);
+        check(even.union(&ends_c).unwrap(), |a, b| a || b);
+        check(even.difference(&ends_c).unwrap(), |a, b| a && !b);
+        check(even.symmetric_difference(&ends_c).unwrap(), |a, b| a != b);
+    }
+
+    #[test]
+    fn test_without_alphabets() {
+        let a = read("- 0 1 E\n+ 1 1 E\n");
+        let b = read("- 0 E 1\n+ 1 E 1\n");
+        let union = a.union(&b).unwrap();
+        assert!(union.alphabet().is_empty());
+        assert_eq!(union.rows().len(), 3);
+        assert!(union
+            .intersect(&a)
+            .unwrap()
+            .difference(&a)
+            .unwrap()
+            .is_empty_language());
+        assert!(a.intersect(&b).unwrap().is_empty_language());
+        assert!(a
+            .intersect(&Table::from(Vec::new()))
+            .unwrap()
+            .is_empty_language());
+    }
+
+    #[test]
+    fn test_one_side_without_alphabet() {
+        let a = read("DFA 2 2 a b\n- 0 1 E\n+ 1 E E\n");
+        let b = read("- 0 E 1\n+ 1 E E\n");
+        for union in &[a.union(&b).unwrap(), b.union(&a).unwrap()] {
+            assert_eq!(union.alphabet().symbols(), &['a', 'b']);
+            assert_eq!(union.does_match("a"), MatchOutcome::Accepted { state: 1 });
+            assert!(union.does_match("b").is_accepted());
+            assert!(!union.does_match("ab").is_accepted());
+        }
+        assert_eq!(
+            a.union(&Table::from(Vec::new())).unwrap().alphabet(),
+            a.alphabet()
+        );
+
+        let narrow = read("- 0 0\n");
+        assert_eq!(
+            a.union(&narrow).err(),
+            Some(AlphabetWidthError {
+                transitions: 1,
+                symbols: 2
+            })
+        );
+        assert!(narrow.intersect(&a).is_err());
+    }
+}
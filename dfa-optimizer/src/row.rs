@@ -3,37 +3,30 @@ use std::fmt;
 use std::ops::{Index, IndexMut};
 use std::str::FromStr;
 
-#[derive(Default, Debug, Clone)]
+use crate::error::RowParseError;
+
+/// With the `serde` feature a row is `{"accepting", "id", "transitions",
+/// "token"}`, where a missing transition is `null` and `token` is left out
+/// when there is none.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Row {
+    #[cfg_attr(feature = "serde", serde(rename = "accepting"))]
     accepting_state: bool,
     pub id: usize,
     transitions: Vec<Option<usize>>,
+    // Which token an accepting row recognizes, for scanner tables. Lower ids
+    // have higher priority.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    token: Option<usize>,
 }
 
 impl Row {
-    pub fn from_str_custom(input: &str) -> Result<Row, ()> {
-        debug!("parsing input to row: {}", input);
-        let tokens: Vec<&str> = input.split(' ').collect();
-
-        match tokens.as_slice() {
-            [accept, id, transitions @ ..] => {
-                let is_accept = *accept == "+";
-                let id = id.parse().unwrap();
-                let transitions: Vec<Option<usize>> = transitions
-                    .iter()
-                    .map(|s| {
-                        if *s == "E" {
-                            None
-                        } else {
-                            Some(s.parse().unwrap())
-                        }
-                    })
-                    .collect();
-
-                Ok(Row::new(is_accept, id, transitions))
-            }
-            _ => Err(()),
-        }
+    pub fn from_str_custom(input: &str) -> Result<Row, RowParseError> {
+        input.parse()
     }
 
     pub fn new(accepting_state: bool, id: usize, transitions: Vec<Option<usize>>) -> Self {
@@ -41,6 +34,7 @@ impl Row {
             accepting_state,
             id,
             transitions,
+            token: None,
         }
     }
 
@@ -49,6 +43,7 @@ impl Row {
             accepting_state,
             id,
             transitions: (0..num_transitions).map(|_| None).collect(), // All transitions are None
+            token: None,
         }
     }
 
@@ -60,6 +55,14 @@ impl Row {
         self.accepting_state = accepting;
     }
 
+    pub fn token(&self) -> Option<usize> {
+        self.token
+    }
+
+    pub fn set_token(&mut self, token: Option<usize>) {
+        self.token = token;
+    }
+
     pub fn transitions(&self) -> &[Option<usize>] {
         &self.transitions
     }
@@ -67,6 +70,12 @@ impl Row {
     pub fn transitions_mut(&mut self) -> &mut [Option<usize>] {
         &mut self.transitions
     }
+
+    /// The target on `column`, or `None` if there is none or the row has
+    /// fewer columns.
+    pub fn transition(&self, column: usize) -> Option<usize> {
+        self.transitions.get(column).copied().flatten()
+    }
 }
 
 impl Index<usize> for Row {
@@ -84,40 +93,79 @@ impl IndexMut<usize> for Row {
 }
 
 impl FromStr for Row {
-    type Err = ();
+    type Err = RowParseError;
 
-    fn from_str(input: &str) -> Result<Row, ()> {
+    fn from_str(input: &str) -> Result<Row, RowParseError> {
         debug!("parsing input to row: {}", input);
-        let tokens: Vec<&str> = input.split(' ').collect();
+        let tokens: Vec<(usize, &str)> = tokenize(input).collect();
 
         match tokens.as_slice() {
-            [accept, id, transitions @ ..] => {
-                let is_accept = *accept == "+";
-                let id = id.parse().unwrap();
-                let transitions: Vec<Option<usize>> = transitions
+            [(column, accept), (id_column, id), rest @ ..] => {
+                let is_accept = match *accept {
+                    "+" => true,
+                    "-" => false,
+                    _ => {
+                        return Err(RowParseError::BadMarker {
+                            column: *column,
+                            token: (*accept).to_owned(),
+                        })
+                    }
+                };
+                let id = id.parse().map_err(|_| RowParseError::NonNumericId {
+                    column: *id_column,
+                    token: (*id).to_owned(),
+                })?;
+                // A scanner table tags accepting rows with `@<token>`.
+                let (transitions, token) = match rest.split_last() {
+                    Some(((column, last), transitions)) if last.starts_with('@') => {
+                        let token = last[1..].parse().map_err(|_| RowParseError::BadToken {
+                            column: *column,
+                            token: (*last).to_owned(),
+                        })?;
+                        (transitions, Some(token))
+                    }
+                    _ => (rest, None),
+                };
+                let transitions = transitions
                     .iter()
-                    .map(|s| {
+                    .map(|(column, s)| {
                         if *s == "E" {
-                            None
+                            Ok(None)
                         } else {
-                            Some(s.parse().unwrap())
+                            s.parse()
+                                .map(Some)
+                                .map_err(|_| RowParseError::BadTransition {
+                                    column: *column,
+                                    token: (*s).to_owned(),
+                                })
                         }
                     })
-                    .collect();
+                    .collect::<Result<_, _>>()?;
 
-                Ok(Row::new(is_accept, id, transitions))
+                let mut row = Row::new(is_accept, id, transitions);
+                row.set_token(token);
+                Ok(row)
             }
-            _ => unreachable!(),
+            _ => Err(RowParseError::ShortRow {
+                fields: tokens.len(),
+            }),
         }
     }
 }
 
+/// Splits a line on whitespace, pairing every token with its 1-based
+/// character column. The .nfa parser in nfamatch uses it too.
+pub fn tokenize(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input.split_whitespace().map(move |token| {
+        let offset = token.as_ptr() as usize - input.as_ptr() as usize;
+        (input[..offset].chars().count() + 1, token)
+    })
+}
+
 impl fmt::Display for Row {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let accepting = if self.is_accepting() { "+" } else { "-" };
 
-        // write!(f, "{} ", accepting)?;
-
         let values: Vec<String> = self
             .transitions
             .iter()
@@ -130,12 +178,70 @@ impl fmt::Display for Row {
             })
             .collect();
 
-        write!(
-            f,
-            "{} {} {}",
-            accepting,
-            self.id,
-            values.as_slice().join(" ")
-        )
+        write!(f, "{} {}", accepting, self.id)?;
+        if !values.is_empty() {
+            write!(f, " {}", values.join(" "))?;
+        }
+        if let Some(token) = self.token {
+            write!(f, " @{}", token)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trip() {
+        for line in &["+ 0 1 E 2", "- 3 E E E", "- 0", "+ 1 E 0 @2", "+ 0 @0"] {
+            let row: Row = line.parse().unwrap();
+            assert_eq!(row.to_string(), *line);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "+".parse::<Row>().unwrap_err(),
+            RowParseError::ShortRow { fields: 1 }
+        );
+        assert_eq!(
+            "* 0 1".parse::<Row>().unwrap_err(),
+            RowParseError::BadMarker {
+                column: 1,
+                token: "*".to_owned()
+            }
+        );
+        assert_eq!(
+            "+ x 1".parse::<Row>().unwrap_err(),
+            RowParseError::NonNumericId {
+                column: 3,
+                token: "x".to_owned()
+            }
+        );
+        assert_eq!(
+            "+ 0 1  e".parse::<Row>().unwrap_err(),
+            RowParseError::BadTransition {
+                column: 8,
+                token: "e".to_owned()
+            }
+        );
+        assert_eq!(
+            "+ 0 1 @x".parse::<Row>().unwrap_err(),
+            RowParseError::BadToken {
+                column: 7,
+                token: "@x".to_owned()
+            }
+        );
+        assert_eq!(
+            "+ 0 @1 1".parse::<Row>().unwrap_err(),
+            RowParseError::BadTransition {
+                column: 5,
+                token: "@1".to_owned()
+            }
+        );
     }
 }
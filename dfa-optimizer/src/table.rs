@@ -3,14 +3,72 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::ops::{Index, IndexMut};
 
+use crate::alphabet::Alphabet;
+use crate::error::AlphabetWidthError;
 use crate::row::Row;
 
 pub type State = BTreeSet<usize>;
-pub type Alphabet = Vec<usize>;
+
+/// `merges[row]` are the rows of a table before `Table::optimize` that were
+/// merged into `row`, in increasing order. Dropped rows appear nowhere.
+pub type Merges = Vec<Vec<usize>>;
+
+/// Which algorithm `Table::optimize_with` uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Minimizer {
+    /// Hopcroft's partition refinement, O(n·k·log n).
+    #[default]
+    Hopcroft,
+    /// The original pass-based merging, kept for comparison. It compares
+    /// transitions through pre-merge assignments, so it can miss
+    /// equivalent rows and lose transitions; it does not produce minimal
+    /// tables in general.
+    Legacy,
+}
+
+/// A position in the input of `Table::does_match`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// 0-based index counted in chars.
+    pub char_index: usize,
+    /// The same position as a byte offset into the input.
+    pub byte_offset: usize,
+}
+
+/// The result of `Table::does_match`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOutcome {
+    /// The whole input was consumed and `state` is accepting.
+    Accepted { state: usize },
+    /// The character at `pos` is not in the alphabet.
+    UnknownSymbol { pos: Position, ch: char },
+    /// `state` has no transition on the character at `pos`.
+    NoTransition { pos: Position, state: usize },
+    /// The whole input was consumed but `state` is not accepting.
+    EndedNonAccepting { state: usize },
+}
+
+impl MatchOutcome {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, MatchOutcome::Accepted { .. })
+    }
+
+    /// Where matching failed, unless it failed or succeeded at the end of
+    /// the input.
+    pub fn failed_at(&self) -> Option<Position> {
+        match self {
+            MatchOutcome::UnknownSymbol { pos, .. } | MatchOutcome::NoTransition { pos, .. } => {
+                Some(*pos)
+            }
+            _ => None,
+        }
+    }
+}
 
 pub struct Table {
     row_assignments: Vec<usize>,
     rows: Vec<Row>,
+    alphabet: Alphabet,
 }
 
 impl Table {
@@ -19,6 +77,7 @@ impl Table {
         Self {
             rows,
             row_assignments,
+            alphabet: Alphabet::default(),
         }
     }
 
@@ -27,9 +86,37 @@ impl Table {
         Self {
             rows: Vec::new(),
             row_assignments,
+            alphabet: Alphabet::default(),
         }
     }
 
+    /// Sets the alphabet without checking it against the rows; see
+    /// `set_alphabet` for that. Symbols past the end of a row have no
+    /// transition.
+    pub fn with_alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    /// Sets the alphabet if it has a symbol for every transition of the
+    /// rows. Any alphabet fits a table without rows.
+    pub fn set_alphabet(&mut self, alphabet: Alphabet) -> Result<(), AlphabetWidthError> {
+        if let Some(row) = self.rows.first() {
+            if row.transitions().len() != alphabet.len() {
+                return Err(AlphabetWidthError {
+                    transitions: row.transitions().len(),
+                    symbols: alphabet.len(),
+                });
+            }
+        }
+        self.alphabet = alphabet;
+        Ok(())
+    }
+
     pub fn rows(&self) -> &[Row] {
         &self.rows
     }
@@ -42,50 +129,316 @@ impl Table {
         self.rows.push(row);
     }
 
-    pub fn does_match(&self, input: &str, mapping: &BTreeMap<char, usize>) -> Option<usize> {
-        debug!("running does match on input: {:?}", input);
-        if self.rows.is_empty() {
-            debug!("rows are empty");
-            return Some(0);
+    /// A table for the empty language: one non-accepting row without
+    /// transitions.
+    pub fn empty_language(alphabet: Alphabet) -> Self {
+        Table::from(vec![Row::blank_row(false, 0, alphabet.len())]).with_alphabet(alphabet)
+    }
+
+    /// Whether every row has a transition on every symbol, so no input can
+    /// fall off the table.
+    pub fn is_complete(&self) -> bool {
+        !self.rows.is_empty()
+            && self
+                .rows
+                .iter()
+                .all(|row| row.transitions().iter().all(Option::is_some))
+    }
+
+    /// Points every missing transition at a new non-accepting sink row
+    /// that loops to itself, and returns the sink's id. A table without
+    /// rows becomes just the sink. Complete tables are left alone and
+    /// return `None`. `optimize` removes the sink again.
+    pub fn complete(&mut self) -> Option<usize> {
+        if self.is_complete() {
+            return None;
+        }
+
+        let width = self
+            .rows
+            .first()
+            .map_or(self.alphabet.len(), |row| row.transitions().len());
+        let sink = self.rows.len();
+        for row in &mut self.rows {
+            for transition in row.transitions_mut() {
+                transition.get_or_insert(sink);
+            }
+        }
+        self.rows
+            .push(Row::new(false, sink, vec![Some(sink); width]));
+        self.row_assignments = (0..self.rows.len()).collect();
+
+        Some(sink)
+    }
+
+    /// The strings over the table's alphabet that the table does not
+    /// match. The copy is completed first, since flipping accepting rows
+    /// alone would still reject inputs that hit a missing transition.
+    /// Tokens are not kept.
+    pub fn complement(&self) -> Table {
+        let mut table = Table::from(self.rows.clone()).with_alphabet(self.alphabet.clone());
+        table.complete();
+        for row in table.rows_mut() {
+            let accepting = row.is_accepting();
+            row.set_accepting(!accepting);
+            row.set_token(None);
         }
+        table
+    }
 
-        if input.is_empty() && !self.rows[0].is_accepting() {
-            debug!("accepting empty state");
-            return Some(0);
+    /// Whether the start row accepts, i.e. the empty string is matched.
+    pub fn accepts_empty_string(&self) -> bool {
+        self.rows.first().is_some_and(|row| row.is_accepting())
+    }
+
+    /// Whether no accepting row can be reached from the start row.
+    pub fn is_empty_language(&self) -> bool {
+        let mut seen = vec![false; self.rows.len()];
+        let mut stack = vec![0];
+        while let Some(row) = stack.pop() {
+            if row >= self.rows.len() || seen[row] {
+                continue;
+            }
+            if self.rows[row].is_accepting() {
+                return false;
+            }
+            seen[row] = true;
+            stack.extend(self.rows[row].transitions().iter().flatten());
         }
+        true
+    }
 
+    /// Runs the table on `input`. A table without rows matches nothing and
+    /// behaves as if it had one non-accepting row without transitions.
+    pub fn does_match(&self, input: &str) -> MatchOutcome {
+        debug!("running does match on input: {:?}", input);
         let mut current_state = 0;
-        let mut chars = input.char_indices();
-
-        while let Some((n, character)) = chars.next() {
-            debug!("{:?}", (n, character));
-            let transition = mapping.get(&character);
-
-            if let Some(&transition) = transition {
-                // If the current character matches some transition,
-                // the option will be some:
-                if let Some(next_state) = self[current_state][transition] {
-                    // if let Some(next_state) = self[current_state][transition] {
-                    current_state = next_state;
-                } else {
-                    // Match failed, return the character that caused it to fail:
-                    return Some(n + 1);
+
+        for (char_index, (byte_offset, ch)) in input.char_indices().enumerate() {
+            debug!("{:?}", (char_index, byte_offset, ch));
+            let pos = Position {
+                char_index,
+                byte_offset,
+            };
+
+            let transition = match self.alphabet.column(ch) {
+                Some(transition) => transition,
+                None => return MatchOutcome::UnknownSymbol { pos, ch },
+            };
+
+            match self
+                .rows
+                .get(current_state)
+                .and_then(|row| row.transition(transition))
+            {
+                Some(next_state) => current_state = next_state,
+                None => {
+                    return MatchOutcome::NoTransition {
+                        pos,
+                        state: current_state,
+                    }
                 }
-            } else {
-                return Some(n + 1);
             }
         }
 
         // If we end at an accepting state we have matched the characters.
-        if self[current_state].is_accepting() {
-            None
-        } else {
-            Some(input.len() + 1)
+        match self.rows.get(current_state) {
+            Some(row) if row.is_accepting() => MatchOutcome::Accepted {
+                state: current_state,
+            },
+            _ => MatchOutcome::EndedNonAccepting {
+                state: current_state,
+            },
         }
     }
 
-    pub fn optimize(&mut self) {
+    /// Minimizes the table: rows that cannot reach an accepting row or
+    /// cannot be reached from row 0 are dropped, equivalent rows are
+    /// merged, and the result is numbered in breadth-first order from row 0.
+    /// The result has exactly one row per Myhill-Nerode class of the
+    /// table's language (ignoring the dead class).
+    ///
+    /// Returns, for every row of the result, the rows of the original table
+    /// that were merged into it.
+    pub fn optimize(&mut self) -> Merges {
+        self.optimize_with(Minimizer::Hopcroft)
+    }
+
+    pub fn optimize_with(&mut self, minimizer: Minimizer) -> Merges {
+        match minimizer {
+            Minimizer::Hopcroft => self.optimize_hopcroft(),
+            Minimizer::Legacy => {
+                let before = self.rows.clone();
+                self.optimize_legacy();
+                trace_merges(&before, &self.rows)
+            }
+        }
+    }
+
+    fn optimize_hopcroft(&mut self) -> Merges {
         info!("un-optimized table: \n{}", self);
+        let (rows, merges) = self.hopcroft();
+        self.rows = rows;
+        self.row_assignments = (0..self.rows.len()).collect();
+        info!("Optimized Table: \n{}", self);
+        merges
+    }
+
+    /// Runs Hopcroft's algorithm on the table completed with an implicit
+    /// sink row, so `None` transitions and dead rows end up in the sink's
+    /// block and are removed together.
+    fn hopcroft(&self) -> (Vec<Row>, Merges) {
+        let n = self.rows.len();
+        if n == 0 {
+            // No rows is the empty language, see `empty_language`.
+            return (
+                vec![Row::blank_row(false, 0, self.alphabet.len())],
+                vec![Vec::new()],
+            );
+        }
+        let sink = n;
+        let num_states = n + 1;
+        let alpha_len = self.rows[0].transitions().len();
+
+        let target = |state: usize, c: usize| -> usize {
+            if state == sink {
+                return sink;
+            }
+            match self.rows[state][c] {
+                Some(t) if t < n => t,
+                _ => sink,
+            }
+        };
+
+        // inverse[c][t] = states that move to t on c
+        let mut inverse = vec![vec![Vec::new(); num_states]; alpha_len];
+        for state in 0..num_states {
+            for (c, inv) in inverse.iter_mut().enumerate() {
+                inv[target(state, c)].push(state);
+            }
+        }
+
+        let mut partition = Partition::new(num_states);
+
+        // Accepting rows of different tokens must never be merged, so the
+        // initial blocks are keyed on both.
+        let mut initial: BTreeMap<(bool, Option<usize>), Vec<usize>> = BTreeMap::new();
+        for state in 0..num_states {
+            let key = if state == sink {
+                (false, None)
+            } else {
+                (self.rows[state].is_accepting(), self.rows[state].token())
+            };
+            initial.entry(key).or_default().push(state);
+        }
+        partition.set_initial(initial.into_values());
+
+        // Every block gets one flag per symbol, at block * alpha_len + c.
+        let mut waiting: Vec<(usize, usize)> = Vec::new();
+        let mut in_waiting = vec![false; num_states * alpha_len];
+        for block in 0..partition.len() {
+            for c in 0..alpha_len {
+                waiting.push((block, c));
+                in_waiting[block * alpha_len + c] = true;
+            }
+        }
+
+        while let Some((splitter, c)) = waiting.pop() {
+            in_waiting[splitter * alpha_len + c] = false;
+
+            let members: Vec<usize> = partition.members(splitter).to_vec();
+            for state in members {
+                for &pred in &inverse[c][state] {
+                    partition.mark(pred);
+                }
+            }
+
+            for (block, new_block) in partition.split_marked() {
+                let smaller =
+                    if partition.members(new_block).len() <= partition.members(block).len() {
+                        new_block
+                    } else {
+                        block
+                    };
+                for d in 0..alpha_len {
+                    if in_waiting[block * alpha_len + d] {
+                        waiting.push((new_block, d));
+                        in_waiting[new_block * alpha_len + d] = true;
+                    } else {
+                        waiting.push((smaller, d));
+                        in_waiting[smaller * alpha_len + d] = true;
+                    }
+                }
+            }
+        }
+
+        let dead = partition.block_of(sink);
+        let start = partition.block_of(0);
+        let merged = |block: usize| -> Vec<usize> {
+            let mut rows: Vec<usize> = partition
+                .members(block)
+                .iter()
+                .copied()
+                .filter(|s| *s != sink)
+                .collect();
+            rows.sort_unstable();
+            rows
+        };
+        if start == dead {
+            // The empty language keeps an explicit, non-accepting start row.
+            return (
+                vec![Row::blank_row(false, 0, alpha_len)],
+                vec![merged(dead)],
+            );
+        }
+
+        // Number the live blocks breadth-first from the start block.
+        let mut numbering: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut order = vec![start];
+        numbering.insert(start, 0);
+        let mut next = 0;
+        while next < order.len() {
+            let representative = partition.members(order[next])[0];
+            for c in 0..alpha_len {
+                let block = partition.block_of(target(representative, c));
+                if block != dead && !numbering.contains_key(&block) {
+                    numbering.insert(block, order.len());
+                    order.push(block);
+                }
+            }
+            next += 1;
+        }
+
+        let rows = order
+            .iter()
+            .enumerate()
+            .map(|(id, block)| {
+                let representative = partition.members(*block)[0];
+                let old = &self.rows[representative];
+                let transitions = (0..alpha_len)
+                    .map(|c| {
+                        numbering
+                            .get(&partition.block_of(target(representative, c)))
+                            .copied()
+                    })
+                    .collect();
+                let mut row = Row::new(old.is_accepting(), id, transitions);
+                row.set_token(old.token());
+                row
+            })
+            .collect();
+
+        (rows, order.into_iter().map(merged).collect())
+    }
+
+    fn optimize_legacy(&mut self) {
+        info!("un-optimized table: \n{}", self);
+        if self.rows.is_empty() {
+            // No rows is the empty language, see `empty_language`.
+            self.rows = vec![Row::blank_row(false, 0, self.alphabet.len())];
+            self.row_assignments = vec![0];
+            return;
+        }
         // Optimize until completed
         while self.optimize_step() {}
         // self.remove_dead_state_simple();
@@ -95,6 +448,7 @@ impl Table {
         let Self {
             row_assignments,
             rows,
+            ..
         } = self;
 
         debug!("Alpha assigns after optimize {:?}", row_assignments);
@@ -119,7 +473,7 @@ impl Table {
         self.remove_dead_branches();
 
         // Alpha is just a lookup table for our index optimization.
-        let alpha: Alphabet = (0..self[0].transitions().len()).collect();
+        let alpha: Vec<usize> = (0..self[0].transitions().len()).collect();
         info!("Alphabet: {:?}", alpha);
 
         // The stack of states
@@ -133,14 +487,19 @@ impl Table {
         info!("Partitioning states");
         info!("Alpha assignments: {:?}", self.row_assignments);
 
-        let (accepting_states, na_states): (State, State) = self
-            .rows()
-            .iter()
-            .map(|r| r.id)
-            .partition(|index| self[self.row_assignments[*index]].is_accepting());
+        // Accepting rows of different tokens must never be merged.
+        let mut partitions: BTreeMap<(bool, Option<usize>), State> = BTreeMap::new();
+        for index in self.rows().iter().map(|r| r.id) {
+            let row = &self[self.row_assignments[index]];
+            partitions
+                .entry((row.is_accepting(), row.token()))
+                .or_default()
+                .insert(index);
+        }
 
-        stack.push((accepting_states, 0));
-        stack.push((na_states, 0));
+        for partition in partitions.into_values() {
+            stack.push((partition, 0));
+        }
 
         while let Some((state, idx)) = stack.pop() {
             debug!("char: {}, state: {:?}", idx, state);
@@ -183,6 +542,9 @@ impl Table {
         ret
     }
 
+    /// Removes rows that cannot reach an accepting row. The start row is
+    /// always kept, so a table for the empty language is a single
+    /// non-accepting row without transitions rather than no rows at all.
     pub fn remove_dead_branches(&mut self) {
         let mut marked: Vec<usize> = Vec::new();
         for row in 0..self.rows.len() {
@@ -191,6 +553,7 @@ impl Table {
             }
             self.dead_bfs(row, &mut marked, &mut BTreeSet::new());
         }
+        marked.retain(|row| *row != 0);
         marked.sort();
         while let Some(row) = marked.pop() {
             self.rows.remove(row);
@@ -198,12 +561,7 @@ impl Table {
         self.make_indexable();
     }
 
-    fn dead_bfs(
-        &self,
-        row: usize,
-        mut marked: &mut Vec<usize>,
-        mut seen: &mut BTreeSet<usize>,
-    ) -> bool {
+    fn dead_bfs(&self, row: usize, marked: &mut Vec<usize>, seen: &mut BTreeSet<usize>) -> bool {
         if self.rows[row].is_accepting() {
             return true;
         }
@@ -216,11 +574,8 @@ impl Table {
         let mut is_alive = false;
         seen.insert(row);
 
-        for transition in self.rows[row].transitions() {
-            match transition {
-                Some(t) => is_alive = is_alive | self.dead_bfs(*t, &mut marked, &mut seen),
-                None => {}
-            }
+        for t in self.rows[row].transitions().iter().flatten() {
+            is_alive |= self.dead_bfs(*t, marked, seen)
         }
         if !is_alive {
             marked.push(row);
@@ -246,16 +601,13 @@ impl Table {
                 row.id = *id;
             }
             for transition in row.transitions_mut() {
-                match transition {
-                    Some(t) => {
-                        if let Some(trans) = state_map.get(t) {
-                            *transition = Some(*trans);
-                        } else {
-                            // Get rid of transitions to nodes that do not exist
-                            *transition = None;
-                        }
+                if let Some(t) = transition {
+                    if let Some(trans) = state_map.get(t) {
+                        *transition = Some(*trans);
+                    } else {
+                        // Get rid of transitions to nodes that do not exist
+                        *transition = None;
                     }
-                    None => (),
                 };
             }
         }
@@ -317,11 +669,9 @@ impl Table {
         self.rows.remove(to_remove);
 
         for row in self.rows_mut() {
-            for trans in row.transitions_mut() {
-                if let Some(t) = trans {
-                    if *t == to_remove {
-                        *t = to_keep;
-                    }
+            for t in row.transitions_mut().iter_mut().flatten() {
+                if *t == to_remove {
+                    *t = to_keep;
                 }
             }
         }
@@ -329,6 +679,133 @@ impl Table {
     }
 }
 
+/// A refinable partition of `0..n`. Every block is a contiguous range of
+/// `elements`, and marked states are swapped to the front of their block so
+/// a block can be split in time proportional to its marked states.
+struct Partition {
+    elements: Vec<usize>,
+    location: Vec<usize>,
+    block_of: Vec<usize>,
+    start: Vec<usize>,
+    end: Vec<usize>,
+    marked: Vec<usize>,
+    touched: Vec<usize>,
+}
+
+impl Partition {
+    fn new(n: usize) -> Self {
+        Self {
+            elements: (0..n).collect(),
+            location: (0..n).collect(),
+            block_of: vec![0; n],
+            start: Vec::new(),
+            end: Vec::new(),
+            marked: Vec::new(),
+            touched: Vec::new(),
+        }
+    }
+
+    fn set_initial<I: IntoIterator<Item = Vec<usize>>>(&mut self, blocks: I) {
+        let mut pos = 0;
+        for (block, states) in blocks.into_iter().enumerate() {
+            self.start.push(pos);
+            for state in states {
+                self.elements[pos] = state;
+                self.location[state] = pos;
+                self.block_of[state] = block;
+                pos += 1;
+            }
+            self.end.push(pos);
+            self.marked.push(0);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.start.len()
+    }
+
+    fn block_of(&self, state: usize) -> usize {
+        self.block_of[state]
+    }
+
+    fn members(&self, block: usize) -> &[usize] {
+        &self.elements[self.start[block]..self.end[block]]
+    }
+
+    fn mark(&mut self, state: usize) {
+        let block = self.block_of[state];
+        let first_unmarked = self.start[block] + self.marked[block];
+        let pos = self.location[state];
+        if pos < first_unmarked {
+            return;
+        }
+
+        let other = self.elements[first_unmarked];
+        self.elements.swap(pos, first_unmarked);
+        self.location[other] = pos;
+        self.location[state] = first_unmarked;
+
+        if self.marked[block] == 0 {
+            self.touched.push(block);
+        }
+        self.marked[block] += 1;
+    }
+
+    /// Splits every block with some but not all states marked, moving the
+    /// marked states into a new block. Returns `(old, new)` block pairs.
+    fn split_marked(&mut self) -> Vec<(usize, usize)> {
+        let mut splits = Vec::new();
+        for block in std::mem::take(&mut self.touched) {
+            let marked = std::mem::replace(&mut self.marked[block], 0);
+            if marked == self.end[block] - self.start[block] {
+                continue;
+            }
+
+            let new_block = self.start.len();
+            self.start.push(self.start[block]);
+            self.end.push(self.start[block] + marked);
+            self.marked.push(0);
+            self.start[block] += marked;
+            for &state in &self.elements[self.start[new_block]..self.end[new_block]] {
+                self.block_of[state] = new_block;
+            }
+            splits.push((block, new_block));
+        }
+        splits
+    }
+}
+
+/// Runs both tables side by side from row 0 and pairs up the rows they
+/// visit together. Used for the legacy minimizer, which does not track
+/// which rows it merges.
+fn trace_merges(before: &[Row], after: &[Row]) -> Merges {
+    let mut merges = vec![Vec::new(); after.len()];
+    if before.is_empty() || after.is_empty() {
+        return merges;
+    }
+
+    let mut seen = vec![false; before.len()];
+    let mut stack = vec![(0, 0)];
+    seen[0] = true;
+    while let Some((old, new)) = stack.pop() {
+        merges[new].push(old);
+        for (c, target) in before[old].transitions().iter().enumerate() {
+            let next = after[new].transitions().get(c).copied().flatten();
+            if let (Some(old), Some(new)) = (*target, next) {
+                if old < before.len() && new < after.len() && !seen[old] {
+                    seen[old] = true;
+                    stack.push((old, new));
+                }
+            }
+        }
+    }
+
+    for rows in &mut merges {
+        rows.sort_unstable();
+    }
+    merges
+}
+
 impl From<Vec<Row>> for Table {
     fn from(rows: Vec<Row>) -> Self {
         let len = rows.len();
@@ -359,3 +836,332 @@ impl fmt::Display for Table {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{words, Rng};
+    use std::fs::File;
+
+    fn read(input: &str) -> Table {
+        Table::from_reader(input.as_bytes()).unwrap()
+    }
+
+    fn load(name: &str) -> Table {
+        let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name);
+        Table::from_reader(File::open(path).unwrap()).unwrap()
+    }
+
+    fn optimized(name: &str, minimizer: Minimizer) -> String {
+        let mut table = load(name);
+        table.optimize_with(minimizer);
+        table.to_string()
+    }
+
+    #[test]
+    fn test_hopcroft_matches_expected_files() {
+        for name in &["cblock", "class", "dead_loop", "multiline_comment"] {
+            let expected = load(&format!("{}_opt.dfa", name)).to_string();
+            assert_eq!(
+                optimized(&format!("{}.dfa", name), Minimizer::Hopcroft),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_hopcroft_agrees_with_legacy() {
+        assert_eq!(
+            optimized("dead_loop.dfa", Minimizer::Hopcroft),
+            optimized("dead_loop.dfa", Minimizer::Legacy)
+        );
+    }
+
+    #[test]
+    fn test_hopcroft_keeps_tokens_apart() {
+        let mut table = read("- 0 1 2\n+ 1 E E @0\n+ 2 E E @1\n");
+        table.optimize();
+        assert_eq!(table.rows().len(), 3);
+        assert_eq!(table[1].token(), Some(0));
+        assert_eq!(table[2].token(), Some(1));
+    }
+
+    #[test]
+    fn test_optimize_reports_merges() {
+        let text = "- 0 1 2\n+ 1 1 1\n+ 2 2 2\n- 3 E E\n- 4 3 3\n";
+        let mut table = read(text);
+        assert_eq!(table.optimize(), vec![vec![0], vec![1, 2]]);
+        assert_eq!(table.optimize(), vec![vec![0], vec![1]]);
+
+        // The legacy minimizer does not merge as much, but every row it
+        // keeps is still traced back to a reachable original row.
+        let mut table = read(text);
+        let merges = table.optimize_with(Minimizer::Legacy);
+        assert_eq!(merges.len(), table.rows().len());
+        assert_eq!(merges[0], vec![0]);
+        assert!(merges.iter().flatten().all(|row| *row < 3));
+    }
+
+    #[test]
+    fn test_merges_agree_on_acceptance() {
+        for name in &["cblock", "class", "dead_loop", "multiline_comment"] {
+            let mut table = load(&format!("{}.dfa", name));
+            let before: Vec<bool> = table.rows().iter().map(|r| r.is_accepting()).collect();
+            let merges = table.optimize();
+            assert_eq!(merges.len(), table.rows().len());
+            for (row, merged) in merges.iter().enumerate() {
+                assert!(!merged.is_empty(), "{}", name);
+                assert!(merged
+                    .iter()
+                    .all(|old| before[*old] == table[row].is_accepting()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimize_empty_language() {
+        for minimizer in &[Minimizer::Hopcroft, Minimizer::Legacy] {
+            let mut table = read("- 0 1\n- 1 0\n");
+            table.optimize_with(*minimizer);
+            assert_eq!(table.to_string(), "- 0 E\n");
+            assert!(table.is_empty_language());
+        }
+
+        for minimizer in &[Minimizer::Hopcroft, Minimizer::Legacy] {
+            let mut table = read("DFA 3 0 a b\n");
+            assert_eq!(table.optimize_with(*minimizer), vec![Vec::<usize>::new()]);
+            assert_eq!(table.to_string(), "- 0 E E\n");
+            assert!(table.is_empty_language());
+        }
+    }
+
+    #[test]
+    fn test_complete_and_complement() {
+        let mut table = read("DFA 1 2 a b\n- 0 1 E\n+ 1 E 1\n");
+        assert!(!table.is_complete());
+        assert_eq!(table.complete(), Some(2));
+        assert!(table.is_complete());
+        assert_eq!(table.to_string(), "- 0 1 2\n+ 1 2 1\n- 2 2 2\n");
+        assert_eq!(table.complete(), None);
+
+        // `optimize` drops the sink again.
+        table.optimize();
+        assert_eq!(table.to_string(), "- 0 1 E\n+ 1 E 1\n");
+
+        let complement = table.complement();
+        for input in &["", "b", "ab", "abba", "ba", "aa", "abbab"] {
+            assert_ne!(
+                complement.does_match(input).is_accepted(),
+                table.does_match(input).is_accepted(),
+                "{:?}",
+                input
+            );
+        }
+        assert!(complement
+            .complement()
+            .intersect(&complement)
+            .unwrap()
+            .is_empty_language());
+
+        // No rows is the empty language, whose complement is everything.
+        let everything = Table::from(Vec::new())
+            .with_alphabet("ab".chars().collect())
+            .complement();
+        assert_eq!(everything.to_string(), "+ 0 0 0\n");
+    }
+
+    fn random_table(rng: &mut Rng) -> Table {
+        let num_rows = 1 + rng.below(8);
+        let alpha_len = 1 + rng.below(3);
+        let rows = (0..num_rows)
+            .map(|id| {
+                let transitions = (0..alpha_len)
+                    .map(|_| match rng.below(4) {
+                        0 => None,
+                        _ => Some(rng.below(num_rows)),
+                    })
+                    .collect();
+                Row::new(rng.below(3) == 0, id, transitions)
+            })
+            .collect::<Vec<Row>>();
+        Table::from(rows)
+    }
+
+    /// Runs a table on a word of column indices, `None` meaning rejected.
+    fn accepts(table: &Table, word: &[usize]) -> bool {
+        if table.rows().is_empty() {
+            return false;
+        }
+        let mut state = 0;
+        for &c in word {
+            match table[state][c] {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        table[state].is_accepting()
+    }
+
+    /// Counts the Myhill-Nerode classes of the live, reachable rows by the
+    /// table-filling algorithm: rows are distinguishable if one accepts and
+    /// the other does not, or if some symbol leads to distinguishable rows.
+    /// A missing transition goes to an implicit dead row. The empty language
+    /// still needs its start row.
+    fn minimal_size(table: &Table) -> usize {
+        let n = table.rows().len();
+        let alpha_len = table[0].transitions().len();
+        let dead = n;
+        let next = |s: usize, c: usize| {
+            if s == dead {
+                dead
+            } else {
+                table[s][c].unwrap_or(dead)
+            }
+        };
+        let accepting = |s: usize| s != dead && table[s].is_accepting();
+
+        let mut distinct: Vec<Vec<bool>> = (0..=n)
+            .map(|p| (0..=n).map(|q| accepting(p) != accepting(q)).collect())
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for p in 0..=n {
+                for q in 0..=n {
+                    if !distinct[p][q] && (0..alpha_len).any(|c| distinct[next(p, c)][next(q, c)]) {
+                        distinct[p][q] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut reachable = vec![false; n];
+        let mut stack = vec![0];
+        while let Some(s) = stack.pop() {
+            if s == dead || reachable[s] {
+                continue;
+            }
+            reachable[s] = true;
+            stack.extend((0..alpha_len).map(|c| next(s, c)));
+        }
+
+        let mut representatives: Vec<usize> = Vec::new();
+        for s in (0..n).filter(|s| reachable[*s] && distinct[*s][dead]) {
+            if representatives.iter().all(|r| distinct[*r][s]) {
+                representatives.push(s);
+            }
+        }
+        representatives.len().max(1)
+    }
+
+    #[test]
+    fn test_does_match_multi_byte_positions() {
+        // α β* 🦀 over the alphabet α β 🦀
+        let table = read("DFA 3 3 α β 🦀\n- 0 1 E E\n- 1 E 1 2\n+ 2 E E E\n");
+        let pos = |char_index, byte_offset| Position {
+            char_index,
+            byte_offset,
+        };
+
+        assert_eq!(
+            table.does_match("αββ🦀"),
+            MatchOutcome::Accepted { state: 2 }
+        );
+        assert_eq!(
+            table.does_match("αβα"),
+            MatchOutcome::NoTransition {
+                pos: pos(2, 4),
+                state: 1
+            }
+        );
+        assert_eq!(
+            table.does_match("αβx"),
+            MatchOutcome::UnknownSymbol {
+                pos: pos(2, 4),
+                ch: 'x'
+            }
+        );
+        assert_eq!(
+            table.does_match("αβ"),
+            MatchOutcome::EndedNonAccepting { state: 1 }
+        );
+    }
+
+    #[test]
+    fn test_alphabet_wider_than_rows() {
+        let mut table = read("+ 0 0\n");
+        assert_eq!(
+            table.set_alphabet("ab".chars().collect()),
+            Err(AlphabetWidthError {
+                transitions: 1,
+                symbols: 2
+            })
+        );
+        assert!(table.alphabet().is_empty());
+
+        let table = table.with_alphabet("ab".chars().collect());
+        assert_eq!(table.does_match("aa"), MatchOutcome::Accepted { state: 0 });
+        assert_eq!(
+            table.does_match("b"),
+            MatchOutcome::NoTransition {
+                pos: Position {
+                    char_index: 0,
+                    byte_offset: 0
+                },
+                state: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_does_match_without_rows() {
+        let table = Table::from(Vec::new()).with_alphabet(Alphabet::new(vec!['a']));
+        assert_eq!(
+            table.does_match(""),
+            MatchOutcome::EndedNonAccepting { state: 0 }
+        );
+        assert_eq!(
+            table.does_match("a"),
+            MatchOutcome::NoTransition {
+                pos: Position {
+                    char_index: 0,
+                    byte_offset: 0
+                },
+                state: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_optimize_random_tables() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..500 {
+            let original = random_table(&mut rng);
+            let alpha_len = original[0].transitions().len();
+            let mut table = Table::from(original.rows().to_vec());
+            table.optimize();
+
+            let columns: Vec<usize> = (0..alpha_len).collect();
+            for word in words(&columns, 6) {
+                assert_eq!(
+                    accepts(&original, &word),
+                    accepts(&table, &word),
+                    "{:?} on\n{}optimized to\n{}",
+                    word,
+                    original,
+                    table
+                );
+            }
+            assert_eq!(
+                table.rows().len(),
+                minimal_size(&original),
+                "not minimal:\n{}optimized to\n{}",
+                original,
+                table
+            );
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Helpers shared by the unit tests here and in nfamatch, which turns on
+//! the `test-util` feature for its tests.
+
+/// Every sequence of `symbols` of at most `max_len` items, shortest first.
+pub fn words<T: Clone>(symbols: &[T], max_len: usize) -> Vec<Vec<T>> {
+    let mut all = vec![Vec::new()];
+    let mut last = vec![Vec::new()];
+    for _ in 0..max_len {
+        last = last
+            .iter()
+            .flat_map(|w: &Vec<T>| {
+                symbols.iter().map(move |c| {
+                    let mut w = w.clone();
+                    w.push(c.clone());
+                    w
+                })
+            })
+            .collect();
+        all.extend(last.iter().cloned());
+    }
+    all
+}
+
+/// Every string over `symbols` of at most `max_len` characters.
+pub fn strings(symbols: &[char], max_len: usize) -> Vec<String> {
+    words(symbols, max_len)
+        .into_iter()
+        .map(|w| w.into_iter().collect())
+        .collect()
+}
+
+/// xorshift64*, so the property tests need no extra dependencies.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
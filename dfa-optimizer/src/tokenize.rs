@@ -0,0 +1,156 @@
+use log::*;
+use std::ops::Range;
+
+use crate::table::Table;
+
+/// A piece of input found by `Table::tokenize`. Spans are byte ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// The longest prefix of the remaining input that the table accepts,
+    /// and the accepting row it ended in.
+    Accepted { span: Range<usize>, state: usize },
+    /// A run of input where no non-empty prefix is accepted.
+    Error { span: Range<usize> },
+}
+
+impl Token {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Token::Accepted { span, .. } | Token::Error { span } => span.clone(),
+        }
+    }
+}
+
+/// Iterator returned by `Table::tokenize`.
+pub struct Tokens<'a> {
+    table: &'a Table,
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    /// Runs the table from `start` and returns the end and final row of
+    /// the longest non-empty accepted prefix.
+    fn longest_match(&self, start: usize) -> Option<(usize, usize)> {
+        if self.table.rows().is_empty() {
+            return None;
+        }
+
+        let mut state = 0;
+        let mut last_accept = None;
+        for (n, c) in self.input[start..].char_indices() {
+            let next = self
+                .table
+                .alphabet()
+                .column(c)
+                .and_then(|column| self.table[state].transition(column));
+            match next {
+                Some(next) => state = next,
+                None => break,
+            }
+
+            if self.table[state].is_accepting() {
+                last_accept = Some((start + n + c.len_utf8(), state));
+            }
+        }
+
+        last_accept
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.pos >= self.input.len() {
+            return None;
+        }
+
+        if let Some((end, state)) = self.longest_match(self.pos) {
+            let span = self.pos..end;
+            debug!("accepted {:?} in row {}", span, state);
+            self.pos = end;
+            return Some(Token::Accepted { span, state });
+        }
+
+        // Skip characters until something matches again and report them
+        // as one error.
+        let start = self.pos;
+        while let Some(c) = self.input[self.pos..].chars().next() {
+            self.pos += c.len_utf8();
+            if self.pos >= self.input.len() || self.longest_match(self.pos).is_some() {
+                break;
+            }
+        }
+
+        debug!("error at {:?}", start..self.pos);
+        Some(Token::Error {
+            span: start..self.pos,
+        })
+    }
+}
+
+impl Table {
+    /// Splits `input` into maximal-munch tokens: at each position the
+    /// longest accepted prefix wins. Empty matches are never produced.
+    pub fn tokenize<'a>(&'a self, input: &'a str) -> Tokens<'a> {
+        Tokens {
+            table: self,
+            input,
+            pos: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 0+ or 0+.0+ over the alphabet `0 .`
+    fn numbers() -> Table {
+        Table::from_reader("DFA 3 4 0 .\n- 0 1 E\n+ 1 1 2\n- 2 3 E\n+ 3 3 E\n".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_maximal_munch() {
+        let table = numbers();
+        let tokens: Vec<Token> = table.tokenize("00.0.0").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Accepted {
+                    span: 0..4,
+                    state: 3
+                },
+                Token::Error { span: 4..5 },
+                Token::Accepted {
+                    span: 5..6,
+                    state: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backtracks_to_last_accept() {
+        let table = numbers();
+        let spans: Vec<_> = table.tokenize("0.").map(|t| t.span()).collect();
+        assert_eq!(spans, vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn test_error_spans_are_merged_and_utf8() {
+        let table = numbers();
+        let tokens: Vec<Token> = table.tokenize("xλ0").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Error { span: 0..3 },
+                Token::Accepted {
+                    span: 3..4,
+                    state: 1
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nfamatch::{LazyDfa, Nfa, NfaBuilder};
+
+/// `n` states where every state has lambda edges to the next `fan`
+/// states, loops on `a` and steps back to 0 on `b`. The lambda graph has
+/// far more edges than states, like the `#`-style fans of `cblock.nfa`.
+fn lambda_fan(n: usize, fan: usize) -> Nfa {
+    let mut builder = NfaBuilder::new('L').alphabet("ab".chars()).add_states(n);
+    for state in 0..n {
+        builder = builder
+            .add_transition(state, state, Some('a'))
+            .add_transition(state, 0, Some('b'));
+        for target in state + 1..(state + 1 + fan).min(n) {
+            builder = builder.add_transition(state, target, Some('L'));
+        }
+    }
+    builder.mark_accepting(n - 1).build().unwrap()
+}
+
+fn to_dfa(c: &mut Criterion) {
+    for name in &["float", "3_id", "cblock"] {
+        let path = format!("{}/../{}.nfa", env!("CARGO_MANIFEST_DIR"), name);
+        let nfa = Nfa::from_file(&path).unwrap();
+        c.bench_function(&format!("to_dfa {}", name), |b| {
+            b.iter(|| black_box(&nfa).to_dfa())
+        });
+    }
+
+    let nfa = lambda_fan(200, 20);
+    c.bench_function("to_dfa lambda_fan", |b| b.iter(|| black_box(&nfa).to_dfa()));
+}
+
+/// Matching one long identifier against `3_id.nfa` with the full table,
+/// a warm `LazyDfa` and plain NFA simulation.
+fn matching(c: &mut Criterion) {
+    let path = format!("{}/../3_id.nfa", env!("CARGO_MANIFEST_DIR"));
+    let nfa = Nfa::from_file(&path).unwrap();
+    let input = format!("{}_x_y_", "abcXYZ".repeat(100));
+
+    let table = nfa.to_dfa();
+    c.bench_function("match table", |b| {
+        b.iter(|| table.does_match(black_box(&input)))
+    });
+    let mut lazy = LazyDfa::new(nfa.clone());
+    c.bench_function("match lazy", |b| b.iter(|| lazy.matches(black_box(&input))));
+    c.bench_function("match nfa", |b| b.iter(|| nfa.matches(black_box(&input))));
+}
+
+criterion_group!(benches, to_dfa, matching);
+criterion_main!(benches);
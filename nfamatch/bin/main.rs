@@ -1,10 +1,9 @@
 #![allow(non_snake_case)]
-use nfamatch::Nfa;
-use std::collections::BTreeMap;
+use dfa_optimizer::{Format, MatchOutcome};
+use nfamatch::{Nfa, ParseMode, ParseOptions};
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 use log::*;
@@ -20,6 +19,25 @@ struct Args {
     /// Path to output the optimized DFA
     // #[structopt(short, long)]
     rest: Vec<String>,
+    /// Skip blank lines and `#` comments instead of rejecting them.
+    #[structopt(long)]
+    lenient: bool,
+    /// Size the NFA from the states its rows use when the header count is wrong.
+    #[structopt(long)]
+    auto_size: bool,
+    /// Output format: text or json (json needs the `serde` feature)
+    #[structopt(long, default_value = "text")]
+    format: Format,
+    /// Write the DFA before optimization as Graphviz DOT to this path, the
+    /// optimized DFA to `<path>.opt` and the NFA to `<path>.nfa`.
+    #[structopt(long)]
+    dot: Option<PathBuf>,
+    /// Give up if the DFA needs more than this many states.
+    #[structopt(long)]
+    max_states: Option<usize>,
+    /// Write missing transitions as a sink row instead of `E`.
+    #[structopt(long)]
+    complete: bool,
 }
 
 // cargo run -- --file float.nfa --out out.dfa
@@ -29,35 +47,80 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // TODO: Read Rows and create separate NFA row type.
     info!("Creating NFA table from: {}", args.file.display());
-    let nfa: Nfa = Nfa::from_file(args.file)?;
-    let mut table = nfa.to_dfa();
+    let options = ParseOptions {
+        mode: if args.lenient {
+            ParseMode::Lenient
+        } else {
+            ParseMode::Strict
+        },
+        auto_size: args.auto_size,
+    };
+    let nfa: Nfa = match Nfa::from_file_with(&args.file, options) {
+        Ok(nfa) => nfa,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut table = match nfa.to_dfa_with_limits(args.max_states, None) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(path) = &args.dot {
+        write_dot(&with_suffix(path, ".nfa"), &nfa.to_dot())?;
+        write_dot(path, &table.to_dot())?;
+    }
     info!("Optimizing DFA table");
     table.optimize();
+    if let Some(path) = &args.dot {
+        write_dot(&with_suffix(path, ".opt"), &table.to_dot())?;
+    }
 
-    let dfa_char_map = BTreeMap::from_iter(
-        nfa.character_map()
-            .iter()
-            .filter(|(c, _)| **c != nfa.lambda_char())
-            .map(|(c, i)| (*c, i - 1)),
-    );
     info!("Checking tokens");
     for input in args.rest {
         info!("Checking `{}`", input);
-        match table.does_match(&input, &dfa_char_map) {
-            // don't change these to debug, they are always needed for the script
-            None => println!("OUTPUT :M:"),
-            Some(i) => println!("OUTPUT {}", i),
+        // don't change these to debug, they are always needed for the script
+        match table.does_match(&input) {
+            MatchOutcome::Accepted { .. } => println!("OUTPUT :M:"),
+            // The script expects 0 for a rejected empty string.
+            _ if input.is_empty() => println!("OUTPUT 0"),
+            // Positions are printed 1-based and counted in characters.
+            MatchOutcome::UnknownSymbol { pos, .. } | MatchOutcome::NoTransition { pos, .. } => {
+                println!("OUTPUT {}", pos.char_index + 1)
+            }
+            MatchOutcome::EndedNonAccepting { .. } => {
+                println!("OUTPUT {}", input.chars().count() + 1)
+            }
         }
     }
 
+    if args.complete {
+        table.complete();
+    }
+
     info!("Writing output file: {}", args.out.display());
     let output_file = File::create(args.out)?;
     let mut writer = BufWriter::new(output_file);
-    for row in table.rows() {
-        writeln!(writer, "{}", row)?;
-    }
+    table.write_as(args.format, &mut writer)?;
 
     writer.flush()?;
 
     Ok(())
 }
+
+/// One graph per file, since Graphviz only renders the first graph of a
+/// file.
+fn write_dot(path: &Path, dot: &str) -> std::io::Result<()> {
+    info!("Writing DOT file: {}", path.display());
+    std::fs::write(path, dot)
+}
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
@@ -0,0 +1,199 @@
+use crate::error::NfaBuildError;
+use crate::nfa::Nfa;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Builds an `Nfa` from code instead of a .nfa file.
+///
+/// States are numbered in the order they are added, starting with the start
+/// state 0. Symbol columns follow the declared alphabet if there is one,
+/// otherwise the order in which symbols first appear in transitions. The
+/// lambda character always gets column 0, like in the .nfa header.
+///
+/// ```
+/// use nfamatch::NfaBuilder;
+///
+/// // a(b|λ)
+/// let nfa = NfaBuilder::new('L')
+///     .add_states(3)
+///     .add_transition(0, 1, "a".chars())
+///     .add_transition(1, 2, "bL".chars())
+///     .mark_accepting(2)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(nfa.num_states(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct NfaBuilder {
+    lambda_char: char,
+    alphabet: Option<Vec<char>>,
+    num_states: usize,
+    transitions: Vec<(usize, usize, Vec<char>)>,
+    accepting_states: BTreeSet<usize>,
+}
+
+impl NfaBuilder {
+    pub fn new(lambda_char: char) -> Self {
+        Self {
+            lambda_char,
+            alphabet: None,
+            num_states: 0,
+            transitions: Vec::new(),
+            accepting_states: BTreeSet::new(),
+        }
+    }
+
+    /// Fixes the alphabet and its column order. Transitions on any other
+    /// symbol are rejected by `build`.
+    pub fn alphabet<I: IntoIterator<Item = char>>(mut self, symbols: I) -> Self {
+        self.alphabet = Some(symbols.into_iter().collect());
+        self
+    }
+
+    pub fn add_state(self) -> Self {
+        self.add_states(1)
+    }
+
+    pub fn add_states(mut self, count: usize) -> Self {
+        self.num_states += count;
+        self
+    }
+
+    pub fn add_transition<I: IntoIterator<Item = char>>(
+        mut self,
+        from: usize,
+        to: usize,
+        symbols: I,
+    ) -> Self {
+        self.transitions
+            .push((from, to, symbols.into_iter().collect()));
+        self
+    }
+
+    pub fn mark_accepting(mut self, state: usize) -> Self {
+        self.accepting_states.insert(state);
+        self
+    }
+
+    pub fn build(self) -> Result<Nfa, NfaBuildError> {
+        if self.num_states == 0 {
+            return Err(NfaBuildError::NoStates);
+        }
+
+        let mut character_map = BTreeMap::new();
+        character_map.insert(self.lambda_char, 0);
+        if let Some(alphabet) = &self.alphabet {
+            for c in alphabet {
+                let next = character_map.len();
+                character_map.entry(*c).or_insert(next);
+            }
+        }
+
+        let check_state = |state: usize| {
+            if state < self.num_states {
+                Ok(state)
+            } else {
+                Err(NfaBuildError::UnknownState {
+                    state,
+                    num_states: self.num_states,
+                })
+            }
+        };
+
+        let mut edges = Vec::new();
+        for (from, to, symbols) in &self.transitions {
+            let from = check_state(*from)?;
+            let to = check_state(*to)?;
+            for c in symbols {
+                let next = character_map.len();
+                let index = match character_map.get(c) {
+                    Some(index) => *index,
+                    None if self.alphabet.is_none() => *character_map.entry(*c).or_insert(next),
+                    None => return Err(NfaBuildError::UnknownSymbol { symbol: *c }),
+                };
+                edges.push((from, index, to));
+            }
+        }
+
+        for state in &self.accepting_states {
+            check_state(*state)?;
+        }
+
+        let mut transitions = vec![vec![Vec::new(); character_map.len()]; self.num_states];
+        for (from, index, to) in edges {
+            if !transitions[from][index].contains(&to) {
+                transitions[from][index].push(to);
+            }
+        }
+
+        Ok(Nfa::from_parts(
+            self.lambda_char,
+            transitions,
+            self.accepting_states,
+            character_map,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_matches_file_layout() {
+        let nfa = NfaBuilder::new('L')
+            .alphabet("ab".chars())
+            .add_states(3)
+            .add_transition(0, 1, "a".chars())
+            .add_transition(1, 2, "bL".chars())
+            .mark_accepting(2)
+            .build()
+            .unwrap();
+
+        assert_eq!(nfa.character_map()[&'L'], 0);
+        assert_eq!(nfa.character_map()[&'a'], 1);
+        assert_eq!(nfa.character_map()[&'b'], 2);
+        assert_eq!(nfa.num_states(), 3);
+
+        let table = nfa.to_dfa();
+        assert!(table.does_match("a").is_accepted());
+        assert!(table.does_match("ab").is_accepted());
+        assert!(!table.does_match("b").is_accepted());
+        assert!(!table.does_match("abb").is_accepted());
+    }
+
+    #[test]
+    fn test_unknown_state() {
+        let err = NfaBuilder::new('L')
+            .add_state()
+            .add_transition(0, 1, "a".chars())
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            NfaBuildError::UnknownState {
+                state: 1,
+                num_states: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown_symbol() {
+        let err = NfaBuilder::new('L')
+            .alphabet("a".chars())
+            .add_states(2)
+            .add_transition(0, 1, "b".chars())
+            .build()
+            .unwrap_err();
+        assert_eq!(err, NfaBuildError::UnknownSymbol { symbol: 'b' });
+    }
+
+    #[test]
+    fn test_no_states() {
+        assert_eq!(
+            NfaBuilder::new('L').build().unwrap_err(),
+            NfaBuildError::NoStates
+        );
+    }
+}
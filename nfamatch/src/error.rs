@@ -0,0 +1,325 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where in a .nfa file an error occurred. Lines and columns are 1-based,
+/// columns count characters rather than bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self {
+            path: None,
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub enum NfaParseError {
+    /// The file could not be opened or read.
+    Io { path: PathBuf, source: io::Error },
+    /// The file has no header line.
+    EmptyFile { path: Option<PathBuf> },
+    /// The header has no state count.
+    MissingStateCount { at: Location },
+    /// The state count in the header is not a number.
+    BadStateCount { at: Location, token: String },
+    /// The header has a state count but no lambda character.
+    MissingLambda { at: Location },
+    /// An alphabet symbol in the header is longer than one character.
+    MultiCharSymbol { at: Location, token: String },
+    /// The header lists a symbol twice, the lambda character included.
+    RepeatedSymbol { at: Location, symbol: char },
+    /// A transition uses a symbol that is not in the alphabet.
+    UnknownSymbol { at: Location, symbol: String },
+    /// A blank line is followed by more rows. Strict mode only allows
+    /// blank lines at the end of the file.
+    BlankLine { at: Location },
+    /// A row has fewer than the three required fields (marker, from id,
+    /// to id).
+    ShortRow { at: Location, fields: usize },
+    /// A row does not start with `+` or `-`.
+    BadMarker { at: Location, token: String },
+    /// A state id in a row is not a number.
+    NonNumericId { at: Location, token: String },
+    /// The header declares a different number of states than the rows use.
+    StateCountMismatch {
+        at: Location,
+        declared: usize,
+        found: usize,
+    },
+    /// Two rows of the same state disagree on whether it is accepting.
+    ConflictingMarker {
+        at: Location,
+        state: usize,
+        first_line: usize,
+    },
+    /// A state id is not below the state count declared in the header.
+    StateOutOfRange {
+        at: Location,
+        id: usize,
+        num_states: usize,
+    },
+}
+
+impl NfaParseError {
+    /// The location of the error, if it points into the file.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            NfaParseError::Io { .. } | NfaParseError::EmptyFile { .. } => None,
+            NfaParseError::MissingStateCount { at }
+            | NfaParseError::BadStateCount { at, .. }
+            | NfaParseError::MissingLambda { at }
+            | NfaParseError::MultiCharSymbol { at, .. }
+            | NfaParseError::RepeatedSymbol { at, .. }
+            | NfaParseError::UnknownSymbol { at, .. }
+            | NfaParseError::BlankLine { at }
+            | NfaParseError::ShortRow { at, .. }
+            | NfaParseError::BadMarker { at, .. }
+            | NfaParseError::NonNumericId { at, .. }
+            | NfaParseError::StateCountMismatch { at, .. }
+            | NfaParseError::ConflictingMarker { at, .. }
+            | NfaParseError::StateOutOfRange { at, .. } => Some(at),
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            NfaParseError::Io { .. } | NfaParseError::EmptyFile { .. } => None,
+            NfaParseError::MissingStateCount { at }
+            | NfaParseError::BadStateCount { at, .. }
+            | NfaParseError::MissingLambda { at }
+            | NfaParseError::MultiCharSymbol { at, .. }
+            | NfaParseError::RepeatedSymbol { at, .. }
+            | NfaParseError::UnknownSymbol { at, .. }
+            | NfaParseError::BlankLine { at }
+            | NfaParseError::ShortRow { at, .. }
+            | NfaParseError::BadMarker { at, .. }
+            | NfaParseError::NonNumericId { at, .. }
+            | NfaParseError::StateCountMismatch { at, .. }
+            | NfaParseError::ConflictingMarker { at, .. }
+            | NfaParseError::StateOutOfRange { at, .. } => Some(at),
+        }
+    }
+
+    /// Attaches the file and line to an error produced while parsing a
+    /// single line.
+    pub fn in_file<P: Into<PathBuf>>(mut self, path: P, line: usize) -> Self {
+        let path = path.into();
+        match &mut self {
+            NfaParseError::EmptyFile { path: p } => *p = Some(path),
+            _ => {
+                if let Some(at) = self.location_mut() {
+                    at.path = Some(path);
+                    at.line = line;
+                }
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for NfaParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NfaParseError::Io { path, source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+            NfaParseError::EmptyFile { path: Some(path) } => {
+                write!(f, "{}: file is empty, expected a header", path.display())
+            }
+            NfaParseError::EmptyFile { path: None } => {
+                write!(f, "file is empty, expected a header")
+            }
+            NfaParseError::MissingStateCount { at } => {
+                write!(f, "{}: header is missing the state count", at)
+            }
+            NfaParseError::BadStateCount { at, token } => {
+                write!(f, "{}: state count `{}` is not a number", at, token)
+            }
+            NfaParseError::MissingLambda { at } => {
+                write!(f, "{}: header is missing the lambda character", at)
+            }
+            NfaParseError::MultiCharSymbol { at, token } => write!(
+                f,
+                "{}: alphabet symbol `{}` is not a single character",
+                at, token
+            ),
+            NfaParseError::RepeatedSymbol { at, symbol } => {
+                write!(f, "{}: alphabet symbol `{}` is repeated", at, symbol)
+            }
+            NfaParseError::UnknownSymbol { at, symbol } => {
+                write!(f, "{}: symbol `{}` is not in the alphabet", at, symbol)
+            }
+            NfaParseError::BlankLine { at } => write!(f, "{}: blank line between rows", at),
+            NfaParseError::ShortRow { at, fields } => write!(
+                f,
+                "{}: row has {} field(s), expected a marker, a from id and a to id",
+                at, fields
+            ),
+            NfaParseError::BadMarker { at, token } => {
+                write!(f, "{}: expected `+` or `-`, found `{}`", at, token)
+            }
+            NfaParseError::NonNumericId { at, token } => {
+                write!(f, "{}: state id `{}` is not a number", at, token)
+            }
+            NfaParseError::StateCountMismatch {
+                at,
+                declared,
+                found,
+            } => write!(
+                f,
+                "{}: header declares {} states but the rows use {}",
+                at, declared, found
+            ),
+            NfaParseError::ConflictingMarker {
+                at,
+                state,
+                first_line,
+            } => write!(
+                f,
+                "{}: state {} is marked differently than on line {}",
+                at, state, first_line
+            ),
+            NfaParseError::StateOutOfRange { at, id, num_states } => write!(
+                f,
+                "{}: state {} is out of range, the header declares {} states",
+                at, id, num_states
+            ),
+        }
+    }
+}
+
+impl Error for NfaParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NfaParseError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Errors from `NfaBuilder::build`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NfaBuildError {
+    /// The builder has no states, so there is no start state.
+    NoStates,
+    /// A transition or accepting mark refers to a state that was never added.
+    UnknownState { state: usize, num_states: usize },
+    /// A transition uses a symbol outside the declared alphabet.
+    UnknownSymbol { symbol: char },
+}
+
+impl fmt::Display for NfaBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NfaBuildError::NoStates => write!(f, "the NFA has no start state"),
+            NfaBuildError::UnknownState { state, num_states } => write!(
+                f,
+                "state {} does not exist, the NFA has {} states",
+                state, num_states
+            ),
+            NfaBuildError::UnknownSymbol { symbol } => {
+                write!(f, "symbol `{}` is not in the alphabet", symbol)
+            }
+        }
+    }
+}
+
+impl Error for NfaBuildError {}
+
+/// Errors from `Nfa::to_dfa_with_limits`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeterminizeError {
+    /// The DFA needs more states than allowed; `reached` were built.
+    TooManyStates { reached: usize },
+    /// Determinizing took longer than `limit`, after building `reached`
+    /// states.
+    TookTooLong { reached: usize, limit: Duration },
+}
+
+impl fmt::Display for DeterminizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeterminizeError::TooManyStates { reached } => {
+                write!(f, "the DFA needs more than {} states", reached)
+            }
+            DeterminizeError::TookTooLong { reached, limit } => write!(
+                f,
+                "gave up on the DFA after {:?}, with {} states built",
+                limit, reached
+            ),
+        }
+    }
+}
+
+impl Error for DeterminizeError {}
+
+/// Errors from `regex::compile`. Positions are 0-based character indices
+/// into the pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegexError {
+    /// The pattern ends in the middle of an escape or class.
+    UnexpectedEnd { pos: usize },
+    /// A `(` without a `)`, or a `)` without a `(`.
+    UnmatchedParen { pos: usize },
+    /// A `*`, `+` or `?` with nothing before it.
+    NothingToRepeat { pos: usize, op: char },
+    /// A `[` without a `]`.
+    UnclosedClass { pos: usize },
+    /// A class with no symbols, `[]`.
+    EmptyClass { pos: usize },
+    /// A class range whose end comes before its start, like `z-a`.
+    BadRange { pos: usize, low: char, high: char },
+    /// Negated classes need a fixed alphabet, which patterns do not have.
+    NegatedClass { pos: usize },
+    /// The requested lambda character is also used as a symbol.
+    LambdaInPattern { lambda_char: char },
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegexError::UnexpectedEnd { pos } => {
+                write!(f, "{}: unexpected end of pattern", pos)
+            }
+            RegexError::UnmatchedParen { pos } => write!(f, "{}: unmatched parenthesis", pos),
+            RegexError::NothingToRepeat { pos, op } => {
+                write!(f, "{}: `{}` has nothing to repeat", pos, op)
+            }
+            RegexError::UnclosedClass { pos } => write!(f, "{}: unclosed character class", pos),
+            RegexError::EmptyClass { pos } => write!(f, "{}: empty character class", pos),
+            RegexError::BadRange { pos, low, high } => {
+                write!(f, "{}: range `{}-{}` is out of order", pos, low, high)
+            }
+            RegexError::NegatedClass { pos } => {
+                write!(f, "{}: negated character classes are not supported", pos)
+            }
+            RegexError::LambdaInPattern { lambda_char } => write!(
+                f,
+                "lambda character `{}` is used as a symbol in the pattern",
+                lambda_char
+            ),
+        }
+    }
+}
+
+impl Error for RegexError {}
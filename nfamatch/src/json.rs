@@ -0,0 +1,165 @@
+//! JSON form of `Nfa`, behind the `serde` feature.
+//!
+//! ```json
+//! {
+//!   "lambda": "λ",
+//!   "alphabet": ["a", "b"],
+//!   "states": 3,
+//!   "accepting": [2],
+//!   "transitions": [
+//!     {"from": 0, "to": 1, "symbol": "a"},
+//!     {"from": 1, "to": 2, "symbol": "λ"}
+//!   ],
+//!   "tokens": {"2": 0}
+//! }
+//! ```
+//!
+//! State 0 is the start state. The alphabet does not include `lambda`;
+//! lambda transitions use the `lambda` symbol. `tokens` maps accepting
+//! states to token ids, as set by `Nfa::union_tagged`, and is left out when
+//! empty.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::builder::NfaBuilder;
+use crate::nfa::Nfa;
+
+#[derive(Serialize, Deserialize)]
+struct NfaJson {
+    lambda: char,
+    alphabet: Vec<char>,
+    states: usize,
+    accepting: Vec<usize>,
+    transitions: Vec<TransitionJson>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tokens: BTreeMap<usize, usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransitionJson {
+    from: usize,
+    to: usize,
+    symbol: char,
+}
+
+impl From<&Nfa> for NfaJson {
+    fn from(nfa: &Nfa) -> Self {
+        let mut symbols = vec![nfa.lambda_char(); nfa.character_map().len()];
+        for (c, i) in nfa.character_map() {
+            symbols[*i] = *c;
+        }
+
+        let mut transitions = Vec::new();
+        for (from, row) in nfa.transitions().iter().enumerate() {
+            for (i, targets) in row.iter().enumerate() {
+                for to in targets {
+                    transitions.push(TransitionJson {
+                        from,
+                        to: *to,
+                        symbol: symbols[i],
+                    });
+                }
+            }
+        }
+
+        Self {
+            lambda: nfa.lambda_char(),
+            alphabet: symbols[1..].to_vec(),
+            states: nfa.num_states(),
+            accepting: nfa.accepting_states().iter().copied().collect(),
+            transitions,
+            tokens: nfa.tokens().clone(),
+        }
+    }
+}
+
+impl TryFrom<NfaJson> for Nfa {
+    type Error = String;
+
+    fn try_from(json: NfaJson) -> Result<Self, String> {
+        for (i, c) in json.alphabet.iter().enumerate() {
+            if *c == json.lambda || json.alphabet[..i].contains(c) {
+                return Err(format!("alphabet symbol `{}` is repeated", c));
+            }
+        }
+
+        let mut builder = NfaBuilder::new(json.lambda)
+            .alphabet(json.alphabet)
+            .add_states(json.states);
+        for t in json.transitions {
+            builder = builder.add_transition(t.from, t.to, Some(t.symbol));
+        }
+        for state in json.accepting {
+            builder = builder.mark_accepting(state);
+        }
+        let mut nfa = builder.build().map_err(|e| e.to_string())?;
+
+        if let Some(state) = json.tokens.keys().find(|s| !nfa.is_accepting(**s)) {
+            return Err(format!("state {} has a token but is not accepting", state));
+        }
+        nfa.set_tokens(json.tokens);
+
+        Ok(nfa)
+    }
+}
+
+impl Serialize for Nfa {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NfaJson::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Nfa {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = NfaJson::deserialize(deserializer)?;
+        Nfa::try_from(json).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::Nfa;
+    use crate::regex;
+
+    #[test]
+    fn test_schema() {
+        let nfa = regex::compile_with_lambda("ab?", 'L').unwrap();
+        let json = serde_json::to_value(&nfa).unwrap();
+        assert_eq!(json["lambda"], "L");
+        assert_eq!(json["alphabet"], serde_json::json!(["a", "b"]));
+        assert_eq!(json["states"], nfa.num_states());
+        assert!(json.get("tokens").is_none());
+
+        let back: Nfa = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+        assert_eq!(back.character_map(), nfa.character_map());
+        assert_eq!(back.accepting_states(), nfa.accepting_states());
+    }
+
+    #[test]
+    fn test_tokens_round_trip() {
+        let a = regex::compile("a").unwrap();
+        let b = regex::compile("b").unwrap();
+        let union = Nfa::union_tagged(vec![&a, &b]);
+        let json = serde_json::to_string(&union).unwrap();
+        let back: Nfa = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.tokens(), union.tokens());
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn test_rejects_bad_nfas() {
+        for json in &[
+            r#"{"lambda":"L","alphabet":["a"],"states":0,"accepting":[],"transitions":[]}"#,
+            r#"{"lambda":"L","alphabet":["a"],"states":1,"accepting":[1],"transitions":[]}"#,
+            r#"{"lambda":"L","alphabet":["a"],"states":1,"accepting":[],"transitions":[{"from":0,"to":0,"symbol":"b"}]}"#,
+            r#"{"lambda":"L","alphabet":["a","L"],"states":1,"accepting":[],"transitions":[]}"#,
+            r#"{"lambda":"L","alphabet":[],"states":1,"accepting":[],"transitions":[],"tokens":{"0":0}}"#,
+        ] {
+            assert!(serde_json::from_str::<Nfa>(json).is_err(), "{}", json);
+        }
+    }
+}
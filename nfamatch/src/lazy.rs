@@ -0,0 +1,193 @@
+//! Matching with a DFA that is built while matching.
+
+use crate::nfa::Nfa;
+use crate::state_set::{Interner, StateSet};
+
+/// How many DFA states `LazyDfa::new` keeps before clearing its cache.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// A cached DFA state.
+#[derive(Debug)]
+struct LazyState {
+    accepting: bool,
+    // character index => cached state, `None` until first followed
+    next: Vec<Option<usize>>,
+}
+
+/// Matches against an `Nfa` the way its `to_dfa` table would, but only
+/// determinizes the states that inputs actually reach. States are cached
+/// by their NFA state set. When the cache holds `capacity` states and a
+/// new one is needed, the whole cache is dropped and rebuilt as matching
+/// goes on, so memory stays bounded on NFAs whose DFA would be huge.
+#[derive(Debug)]
+pub struct LazyDfa {
+    nfa: Nfa,
+    accepting: StateSet,
+    capacity: usize,
+    // cached state id => NFA states
+    sets: Interner,
+    states: Vec<LazyState>,
+    clears: usize,
+}
+
+impl LazyDfa {
+    pub fn new(nfa: Nfa) -> Self {
+        Self::with_capacity(nfa, DEFAULT_CAPACITY)
+    }
+
+    /// A lazy DFA that caches at most `capacity` states, or 1 if
+    /// `capacity` is 0.
+    pub fn with_capacity(nfa: Nfa, capacity: usize) -> Self {
+        Self {
+            accepting: nfa.accepting_states().iter().copied().collect(),
+            nfa,
+            capacity: capacity.max(1),
+            sets: Interner::default(),
+            states: Vec::new(),
+            clears: 0,
+        }
+    }
+
+    pub fn nfa(&self) -> &Nfa {
+        &self.nfa
+    }
+
+    /// The number of DFA states in the cache.
+    pub fn num_cached(&self) -> usize {
+        self.states.len()
+    }
+
+    /// How many times the cache was full and got cleared.
+    pub fn clears(&self) -> usize {
+        self.clears
+    }
+
+    /// Whether the NFA accepts `input`. Agrees with `Nfa::matches` and with
+    /// `does_match` on the `to_dfa` table.
+    pub fn matches(&mut self, input: &str) -> bool {
+        let start = self.nfa.lambda_closures().of(0).clone();
+        let mut current = self.state(start);
+
+        for ch in input.chars() {
+            let c = match self.nfa.symbol_index(ch) {
+                Some(c) => c,
+                None => return false,
+            };
+
+            current = match self.states[current].next[c] {
+                Some(next) => next,
+                None => self.determinize(current, c),
+            };
+            // The empty set can never accept again.
+            if self.sets.get(current).is_empty() {
+                return false;
+            }
+        }
+
+        self.states[current].accepting
+    }
+
+    /// Follows character index `c` out of cached state `from`, caching the
+    /// state it reaches.
+    fn determinize(&mut self, from: usize, c: usize) -> usize {
+        let set = self
+            .nfa
+            .lambda_closures()
+            .follow(&self.nfa.follow_char(self.sets.get(from), c));
+
+        let before = self.clears;
+        let to = self.state(set);
+        // After a clear `from` no longer exists.
+        if self.clears == before {
+            self.states[from].next[c] = Some(to);
+        }
+        to
+    }
+
+    /// The cached id of `set`, adding it and clearing the cache first if
+    /// there is no room.
+    fn state(&mut self, set: StateSet) -> usize {
+        let (id, is_new) = self.sets.intern(set);
+        if !is_new {
+            return id;
+        }
+
+        if self.sets.len() > self.capacity {
+            let set = self.sets.get(id).clone();
+            self.sets.clear();
+            self.states.clear();
+            self.clears += 1;
+            self.sets.intern(set);
+        }
+
+        let set = self.sets.get(self.states.len());
+        self.states.push(LazyState {
+            accepting: set.intersects(&self.accepting),
+            next: vec![None; self.nfa.character_map().len()],
+        });
+        self.states.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::regex;
+    use dfa_optimizer::test_util::Rng;
+
+    const INPUTS: &[&str] = &[
+        "", "a", "b", "ab", "ba", "abba", "aaaa", "abcabc", "bbbbab", "abd", "acdde", "x", "aλ",
+    ];
+
+    #[test]
+    fn test_agrees_with_nfa() {
+        for pattern in &["a(b|c)*d+e?", "(a|b)*a(a|b)(a|b)", "(ab|a)*(b|())", "()"] {
+            let nfa = regex::compile(pattern).unwrap();
+            let mut table = nfa.to_dfa();
+            table.optimize();
+
+            for capacity in &[1, 2, DEFAULT_CAPACITY] {
+                let mut lazy = LazyDfa::with_capacity(nfa.clone(), *capacity);
+                // Twice, so the second round runs on cached states.
+                for _ in 0..2 {
+                    for input in INPUTS {
+                        let expected = table.does_match(input).is_accepted();
+                        assert_eq!(lazy.matches(input), expected, "{} {:?}", pattern, input);
+                        assert!(lazy.num_cached() <= *capacity);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_reached_states_are_built() {
+        let path = format!("{}/../3_id.nfa", env!("CARGO_MANIFEST_DIR"));
+        let nfa = Nfa::from_file(&path).unwrap();
+        let full = nfa.to_dfa().rows().len();
+
+        let mut lazy = LazyDfa::new(nfa);
+        assert!(lazy.matches("abc_x_y_"));
+        assert!(!lazy.matches("abc"));
+        assert!(!lazy.matches("$"));
+        assert!(lazy.num_cached() < full);
+        assert_eq!(lazy.clears(), 0);
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        // The DFA for this has over two million states.
+        let pattern = format!("(a|b)*a{}", "(a|b)".repeat(20));
+        let nfa = regex::compile(&pattern).unwrap();
+        let mut lazy = LazyDfa::with_capacity(nfa.clone(), 64);
+
+        // Pseudo-random, so the last 21 characters keep changing.
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        let input: String = (0..500).map(|_| ['a', 'b'][rng.below(2)]).collect();
+        for end in (0..=input.len()).step_by(7) {
+            assert_eq!(lazy.matches(&input[..end]), nfa.matches(&input[..end]));
+            assert!(lazy.num_cached() <= 64);
+        }
+        assert!(lazy.clears() > 0);
+    }
+}
@@ -0,0 +1,241 @@
+//! Combines one NFA per token into a single scanner DFA.
+
+use crate::error::{NfaParseError, RegexError};
+use crate::nfa::{Nfa, ParseOptions};
+use crate::regex;
+use dfa_optimizer::Table as DfaTable;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDef {
+    pub name: String,
+    /// Higher priorities win when a string matches several tokens.
+    pub priority: i32,
+}
+
+/// Collects token definitions. Each token is an NFA with a name and a
+/// priority; tokens with equal priority are ranked by insertion order.
+#[derive(Debug, Clone, Default)]
+pub struct LexerBuilder {
+    tokens: Vec<(TokenDef, Nfa)>,
+}
+
+impl LexerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_nfa<S: Into<String>>(mut self, name: S, priority: i32, nfa: Nfa) -> Self {
+        let def = TokenDef {
+            name: name.into(),
+            priority,
+        };
+        self.tokens.push((def, nfa));
+        self
+    }
+
+    pub fn add_file<S: Into<String>, P: AsRef<Path>, O: Into<ParseOptions>>(
+        self,
+        name: S,
+        priority: i32,
+        path: P,
+        options: O,
+    ) -> Result<Self, NfaParseError> {
+        let nfa = Nfa::from_file_with(path, options)?;
+        Ok(self.add_nfa(name, priority, nfa))
+    }
+
+    pub fn add_regex<S: Into<String>>(
+        self,
+        name: S,
+        priority: i32,
+        pattern: &str,
+    ) -> Result<Self, RegexError> {
+        let nfa = regex::compile(pattern)?;
+        Ok(self.add_nfa(name, priority, nfa))
+    }
+
+    /// Unions the token NFAs under a fresh start state. Token ids in the
+    /// result are positions in `Lexer::tokens`, highest priority first.
+    pub fn build(mut self) -> Lexer {
+        // Stable, so equal priorities keep their insertion order.
+        self.tokens.sort_by_key(|(def, _)| -(def.priority as i64));
+
+        let nfa = Nfa::union_tagged(self.tokens.iter().map(|(_, nfa)| nfa));
+        let tokens = self.tokens.into_iter().map(|(def, _)| def).collect();
+
+        Lexer { nfa, tokens }
+    }
+}
+
+/// The combined NFA of all tokens.
+#[derive(Debug, Clone)]
+pub struct Lexer {
+    nfa: Nfa,
+    tokens: Vec<TokenDef>,
+}
+
+impl Lexer {
+    pub fn nfa(&self) -> &Nfa {
+        &self.nfa
+    }
+
+    pub fn tokens(&self) -> &[TokenDef] {
+        &self.tokens
+    }
+
+    /// Determinizes and optimizes the combined NFA.
+    pub fn to_scanner(&self) -> ScannerTable {
+        let mut table = self.nfa.to_dfa();
+        table.optimize();
+
+        ScannerTable {
+            table,
+            tokens: self.tokens.clone(),
+        }
+    }
+}
+
+/// A DFA whose accepting rows are tagged with the token they recognize.
+pub struct ScannerTable {
+    table: DfaTable,
+    tokens: Vec<TokenDef>,
+}
+
+impl ScannerTable {
+    pub fn table(&self) -> &DfaTable {
+        &self.table
+    }
+
+    pub fn tokens(&self) -> &[TokenDef] {
+        &self.tokens
+    }
+
+    /// The token a row accepts, if it is accepting.
+    pub fn token_of(&self, row: usize) -> Option<&TokenDef> {
+        self.table[row].token().map(|t| &self.tokens[t])
+    }
+
+    /// The token that the whole input matches, if any.
+    pub fn classify(&self, input: &str) -> Option<&TokenDef> {
+        let mut state = 0;
+        for c in input.chars() {
+            let column = self.table.alphabet().column(c)?;
+            state = self.table[state].transition(column)?;
+        }
+        self.token_of(state)
+    }
+}
+
+impl fmt::Display for ScannerTable {
+    /// Writes the table as a .dfa file, which `Table::from_reader` reads
+    /// back. Accepting rows end with `@<token>`, an index into `tokens`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.table.header())?;
+        for row in self.table.rows() {
+            writeln!(f, "{}", row)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scanner() -> ScannerTable {
+        LexerBuilder::new()
+            .add_regex("ident", 0, "[a-z][a-z0-9]*")
+            .unwrap()
+            .add_regex("if", 10, "if")
+            .unwrap()
+            .add_regex("number", 0, "[0-9]+")
+            .unwrap()
+            .build()
+            .to_scanner()
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let lexer = LexerBuilder::new()
+            .add_regex("a", 1, "a")
+            .unwrap()
+            .add_regex("b", 5, "b")
+            .unwrap()
+            .add_regex("c", 1, "c")
+            .unwrap()
+            .build();
+        let names: Vec<&str> = lexer.tokens().iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_classify() {
+        let scanner = scanner();
+        let name = |s| scanner.classify(s).map(|t| t.name.as_str());
+        assert_eq!(name("if"), Some("if"));
+        assert_eq!(name("i"), Some("ident"));
+        assert_eq!(name("iff"), Some("ident"));
+        assert_eq!(name("x1"), Some("ident"));
+        assert_eq!(name("42"), Some("number"));
+        assert_eq!(name("4x"), None);
+        assert_eq!(name(""), None);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let scanner = scanner();
+        let text = scanner.to_string();
+        let table = DfaTable::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(table.rows(), scanner.table().rows());
+        assert_eq!(table.alphabet(), scanner.table().alphabet());
+
+        let reread = ScannerTable {
+            table,
+            tokens: scanner.tokens().to_vec(),
+        };
+        for input in &["if", "iff", "x1", "42", "4x"] {
+            assert_eq!(reread.classify(input), scanner.classify(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip_whitespace_token() {
+        let scanner = LexerBuilder::new()
+            .add_regex("space", 0, "[ ]+")
+            .unwrap()
+            .add_regex("ident", 0, "[a-z]+")
+            .unwrap()
+            .build()
+            .to_scanner();
+        let table = DfaTable::from_reader(scanner.to_string().as_bytes()).unwrap();
+        assert_eq!(table.rows(), scanner.table().rows());
+        assert_eq!(table.alphabet(), scanner.table().alphabet());
+
+        let reread = ScannerTable {
+            table,
+            tokens: scanner.tokens().to_vec(),
+        };
+        for input in &["  ", "ab", "a b"] {
+            assert_eq!(reread.classify(input), scanner.classify(input), "{}", input);
+        }
+        assert_eq!(reread.classify("  ").unwrap().name, "space");
+    }
+
+    #[test]
+    fn test_optimize_keeps_tokens_apart() {
+        // Both tokens are a single symbol, so a plain minimization would
+        // merge their accepting rows.
+        let scanner = LexerBuilder::new()
+            .add_regex("plus", 0, r"\+")
+            .unwrap()
+            .add_regex("minus", 0, "-")
+            .unwrap()
+            .build()
+            .to_scanner();
+        assert_eq!(scanner.classify("+").unwrap().name, "plus");
+        assert_eq!(scanner.classify("-").unwrap().name, "minus");
+    }
+}
@@ -1,5 +1,17 @@
+pub mod builder;
+pub mod error;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod lazy;
+pub mod lexer;
 pub mod nfa;
-pub use nfa::Nfa;
+pub mod regex;
+pub mod state_set;
+pub use builder::NfaBuilder;
+pub use error::{DeterminizeError, NfaBuildError, NfaParseError, RegexError};
+pub use lazy::LazyDfa;
+pub use lexer::{Lexer, LexerBuilder, ScannerTable, TokenDef};
+pub use nfa::{Nfa, ParseMode, ParseOptions};
 pub mod row;
 
 #[cfg(test)]
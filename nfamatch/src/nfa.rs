@@ -1,15 +1,52 @@
+use crate::error::{DeterminizeError, Location, NfaParseError};
+use crate::regex::DEFAULT_LAMBDA;
 use crate::row::Row;
-use dfa_optimizer::{Row as DfaRow, Table as DfaTable};
+use crate::state_set::Interner;
+use dfa_optimizer::row::tokenize;
+use dfa_optimizer::{dot, Row as DfaRow, Table as DfaTable};
 use log::*;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::iter::FromIterator;
 use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use log::debug;
 
-pub type StateSet = BTreeSet<usize>;
+pub use crate::state_set::StateSet;
+
+/// How `Nfa::from_file_with` treats lines that are not transition rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Every line after the header must be a row, except for blank lines
+    /// at the end of the file.
+    #[default]
+    Strict,
+    /// Blank lines, and lines starting with `#` when `#` is not part of the
+    /// alphabet, are skipped.
+    Lenient,
+}
+
+/// Options for `Nfa::from_file_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    /// Size the NFA from the states the rows actually use instead of
+    /// rejecting files whose header count disagrees.
+    pub auto_size: bool,
+}
+
+impl From<ParseMode> for ParseOptions {
+    fn from(mode: ParseMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct Nfa {
@@ -19,11 +56,119 @@ pub struct Nfa {
     transitions: Vec<Vec<Vec<usize>>>, // potentially refactor this to map?
     accepting_states: BTreeSet<usize>,
     character_map: BTreeMap<char, usize>,
+    // accepting state => token id, only set on NFAs built by `union_tagged`.
+    tokens: BTreeMap<usize, usize>,
+    // state => .nfa lines of the rows leaving it, only set by `from_file`.
+    lines: Vec<Vec<usize>>,
+    // Built by the first `lambda_closures` call, the transitions never
+    // change after construction.
+    closures: OnceLock<LambdaClosures>,
 }
 
 impl Nfa {
-    pub fn new() -> Self {
-        todo!()
+    /// An NFA with a single, non-accepting start state and no transitions.
+    pub fn new(lambda_char: char) -> Self {
+        let mut character_map = BTreeMap::new();
+        character_map.insert(lambda_char, 0);
+
+        Self {
+            lambda_char,
+            transitions: vec![vec![Vec::new()]],
+            accepting_states: BTreeSet::new(),
+            character_map,
+            tokens: BTreeMap::new(),
+            lines: Vec::new(),
+            closures: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn from_parts(
+        lambda_char: char,
+        transitions: Vec<Vec<Vec<usize>>>,
+        accepting_states: BTreeSet<usize>,
+        character_map: BTreeMap<char, usize>,
+    ) -> Self {
+        Self {
+            lambda_char,
+            transitions,
+            accepting_states,
+            character_map,
+            tokens: BTreeMap::new(),
+            lines: Vec::new(),
+            closures: OnceLock::new(),
+        }
+    }
+
+    /// Combines several NFAs under a fresh start state 0 with lambda edges
+    /// into each of them. Every accepting state of the NFA at position `i`
+    /// is tagged with token `i`, and `to_dfa` gives each accepting DFA row
+    /// the lowest token id among its NFA states, so earlier NFAs win ties.
+    ///
+    /// The alphabet is the union of the alphabets, in order of first
+    /// appearance. The lambda character is the first NFA's, unless another
+    /// NFA uses it as a symbol.
+    pub fn union_tagged<'a, I: IntoIterator<Item = &'a Nfa>>(nfas: I) -> Self {
+        let nfas: Vec<&Nfa> = nfas.into_iter().collect();
+
+        let mut symbols: Vec<char> = Vec::new();
+        for nfa in &nfas {
+            let mut own: Vec<(&char, &usize)> = nfa
+                .character_map
+                .iter()
+                .filter(|(c, _)| **c != nfa.lambda_char)
+                .collect();
+            own.sort_by_key(|(_, i)| **i);
+            for (c, _) in own {
+                if !symbols.contains(c) {
+                    symbols.push(*c);
+                }
+            }
+        }
+
+        let preferred = nfas.first().map_or(DEFAULT_LAMBDA, |nfa| nfa.lambda_char);
+        let lambda_char = fresh_lambda(&BTreeSet::from_iter(symbols.iter().copied()), preferred);
+
+        let mut character_map = BTreeMap::new();
+        character_map.insert(lambda_char, 0);
+        for c in symbols {
+            let next = character_map.len();
+            character_map.insert(c, next);
+        }
+
+        let mut union = Nfa::new(lambda_char);
+        union.transitions[0] = vec![Vec::new(); character_map.len()];
+        union.character_map = character_map;
+
+        for (token, nfa) in nfas.into_iter().enumerate() {
+            let base = union.transitions.len();
+            // Column of every symbol of this NFA in the union.
+            let mut columns = vec![0; nfa.character_map.len()];
+            for (c, i) in nfa.character_map.iter() {
+                if *c != nfa.lambda_char {
+                    columns[*i] = union.character_map[c];
+                }
+            }
+
+            union.transitions[0][0].push(base);
+            for row in &nfa.transitions {
+                let mut new_row = vec![Vec::new(); union.character_map.len()];
+                for (i, targets) in row.iter().enumerate() {
+                    new_row[columns[i]].extend(targets.iter().map(|t| t + base));
+                }
+                union.transitions.push(new_row);
+            }
+
+            for state in &nfa.accepting_states {
+                union.accepting_states.insert(state + base);
+                union.tokens.insert(state + base, token);
+            }
+        }
+
+        union
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.transitions.len()
     }
 
     pub fn lambda_char(&self) -> char {
@@ -34,147 +179,552 @@ impl Nfa {
         &self.character_map
     }
 
+    pub fn accepting_states(&self) -> &BTreeSet<usize> {
+        &self.accepting_states
+    }
+
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting_states.contains(&state)
+    }
+
+    /// The 1-based lines of the .nfa file whose rows leave `state`. Empty
+    /// for NFAs that were not read from a file.
+    pub fn source_lines(&self, state: usize) -> &[usize] {
+        self.lines.get(state).map_or(&[], |lines| lines.as_slice())
+    }
+
+    /// `transitions()[state][i]` are the targets of `state` on the symbol
+    /// with character index `i`.
+    #[cfg(feature = "serde")]
+    pub(crate) fn transitions(&self) -> &[Vec<Vec<usize>>] {
+        &self.transitions
+    }
+
+    /// Token ids of accepting states, see `union_tagged`.
+    pub fn tokens(&self) -> &BTreeMap<usize, usize> {
+        &self.tokens
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn set_tokens(&mut self, tokens: BTreeMap<usize, usize>) {
+        self.tokens = tokens;
+    }
+
     pub fn to_dfa(&self) -> DfaTable {
+        self.to_dfa_with_provenance().0
+    }
+
+    /// Like `to_dfa`, but also returns the set of NFA states each DFA row
+    /// stands for, indexed by row. A row accepts because its set contains
+    /// an accepting NFA state.
+    pub fn to_dfa_with_provenance(&self) -> (DfaTable, Vec<StateSet>) {
+        match self.determinize(None, None) {
+            Ok(result) => result,
+            Err(_) => unreachable!("determinizing without limits cannot fail"),
+        }
+    }
+
+    /// Like `to_dfa`, but gives up once the DFA would need more than
+    /// `max_states` rows or determinizing has taken longer than
+    /// `max_duration`. `None` means no limit.
+    pub fn to_dfa_with_limits(
+        &self,
+        max_states: Option<usize>,
+        max_duration: Option<Duration>,
+    ) -> Result<DfaTable, DeterminizeError> {
+        let deadline = max_duration.map(|limit| (Instant::now() + limit, limit));
+        self.determinize(max_states, deadline)
+            .map(|(table, _)| table)
+    }
+
+    /// The subset construction behind `to_dfa_with_provenance` and
+    /// `to_dfa_with_limits`. The deadline is checked once per row.
+    fn determinize(
+        &self,
+        max_states: Option<usize>,
+        deadline: Option<(Instant, Duration)>,
+    ) -> Result<(DfaTable, Vec<StateSet>), DeterminizeError> {
         info!("character map: {:?} ", self.character_map());
         info!("self at the start of to_dfa {:#?}", self);
         let mut dfa_char_map = self.character_map().clone();
         dfa_char_map.remove(&self.lambda_char);
         let alpha_len = dfa_char_map.len(); // length of the new dfa alphabet
 
-        let mut dfa_rows = Vec::new();
-        let mut seen_states: BTreeMap<StateSet, usize> = BTreeMap::new();
-        let mut row_number = 0;
-
-        // TODO: Stack of &StateSet
+        let closures = self.lambda_closures();
+        let accepting: StateSet = self.accepting_states.iter().copied().collect();
+        // Each DFA row's NFA state set is stored once, under the row number.
+        let mut seen_states = Interner::default();
+        let mut dfa_rows: Vec<DfaRow> = Vec::new();
         let mut states_to_process = Vec::new();
-        let mut initial_state = BTreeSet::new();
 
+        let mut initial_state = StateSet::new();
         initial_state.insert(0); // insert starting node
-        initial_state = self.follow_lambda(&initial_state);
+        let initial_state = closures.follow(&initial_state);
         debug!("Initial Lambda Closure: {:?}", initial_state);
 
-        let initial_lambda_accepting = initial_state
-            .intersection(&self.accepting_states)
-            .next()
-            .is_some();
-
-        let new_row = DfaRow::blank_row(initial_lambda_accepting, row_number, alpha_len);
+        if max_states == Some(0) {
+            return Err(DeterminizeError::TooManyStates { reached: 0 });
+        }
+        let mut new_row = DfaRow::blank_row(initial_state.intersects(&accepting), 0, alpha_len);
+        new_row.set_token(self.token_of(&initial_state));
         dfa_rows.push(new_row);
-        seen_states.insert(initial_state.clone(), row_number);
-        states_to_process.push(initial_state);
-        row_number += 1;
+        let (initial_row, _) = seen_states.intern(initial_state);
+        states_to_process.push(initial_row);
+
+        while let Some(current_row) = states_to_process.pop() {
+            if let Some((deadline, limit)) = deadline {
+                if Instant::now() >= deadline {
+                    return Err(DeterminizeError::TookTooLong {
+                        reached: dfa_rows.len(),
+                        limit,
+                    });
+                }
+            }
 
-        while let Some(next_state_to_process) = states_to_process.pop() {
-            debug!("Next State: {:?}", next_state_to_process);
+            debug!("Next State: {:?}", seen_states.get(current_row));
             for character in dfa_char_map.values() {
                 let lambda_closure =
-                    self.follow_lambda(&self.follow_char(&next_state_to_process, *character));
+                    closures.follow(&self.follow_char(seen_states.get(current_row), *character));
                 debug!("{} => {:?}", character, lambda_closure);
 
-                let lambda_clone = lambda_closure.clone();
-
-                if !seen_states.contains_key(&lambda_closure) {
-                    debug!("Lambda closure in seen_states {:?}of", lambda_closure);
-                    let accepting_state = lambda_closure
-                        .intersection(&self.accepting_states)
-                        .next()
-                        .is_some();
-
+                let accepting_state = lambda_closure.intersects(&accepting);
+                let token = self.token_of(&lambda_closure);
+                let (transition, is_new) = seen_states.intern(lambda_closure);
+                if is_new {
+                    if max_states.is_some_and(|max| dfa_rows.len() >= max) {
+                        return Err(DeterminizeError::TooManyStates {
+                            reached: dfa_rows.len(),
+                        });
+                    }
                     info!("Is the new row an accepting state? {}", accepting_state);
-                    let new_row = DfaRow::blank_row(accepting_state, row_number, alpha_len);
-
+                    let mut new_row = DfaRow::blank_row(accepting_state, transition, alpha_len);
+                    new_row.set_token(token);
                     dfa_rows.push(new_row);
-
-                    seen_states.insert(lambda_closure.clone(), row_number);
-                    states_to_process.push(lambda_closure);
-
-                    row_number += 1;
+                    states_to_process.push(transition);
                 }
 
-                let current_row = seen_states[&next_state_to_process];
-                let transition = seen_states[&lambda_clone];
                 dfa_rows[current_row][*character - 1] = Some(transition);
             }
         }
 
+        // Column `i - 1` holds the symbol at character index `i`.
+        let mut symbols: Vec<(usize, char)> = dfa_char_map.iter().map(|(c, i)| (*i, *c)).collect();
+        symbols.sort();
+
         let len = dfa_rows.len();
-        DfaTable::new(dfa_rows, len)
+        let table = DfaTable::new(dfa_rows, len)
+            .with_alphabet(symbols.into_iter().map(|(_, c)| c).collect());
+        Ok((table, seen_states.into_sets()))
     }
 
-    /*
-     * returns the set of NFA states encountered by
-     * recursively following only λ transitions.
-     */
-    fn follow_lambda(&self, states: &StateSet) -> StateSet {
-        let mut states_to_process = Vec::from_iter(states.into_iter());
-        let mut lambda_closure = StateSet::new();
-        while let Some(t) = states_to_process.pop() {
-            lambda_closure.insert(*t);
-            for l_tran in self.transitions[*t][0].iter() {
-                if !lambda_closure.contains(&l_tran) {
-                    lambda_closure.insert(*l_tran);
-                    states_to_process.push(l_tran);
+    /// Whether the NFA accepts `input`, found by tracking the set of states
+    /// the NFA can be in rather than building a DFA first. Each character
+    /// costs at most one pass over the transitions, so this also works on
+    /// NFAs whose DFA would be too big to build. The lambda closures are
+    /// computed by the first call and reused by later ones. Characters
+    /// outside the alphabet, including the lambda character, are rejected.
+    pub fn matches(&self, input: &str) -> bool {
+        let closures = self.lambda_closures();
+        let mut states = closures.of(0).clone();
+
+        for ch in input.chars() {
+            let c = match self.symbol_index(ch) {
+                Some(c) => c,
+                None => return false,
+            };
+
+            states = closures.follow(&self.follow_char(&states, c));
+            if states.is_empty() {
+                return false;
+            }
+        }
+
+        self.accepting_states.iter().any(|s| states.contains(*s))
+    }
+
+    /// The NFA as a DOT digraph. Edges between the same two states are
+    /// merged into one, labelled with all of their symbols; lambda edges
+    /// are labelled with the lambda character.
+    pub fn to_dot(&self) -> String {
+        let mut symbols = vec![self.lambda_char; self.character_map.len()];
+        for (c, i) in &self.character_map {
+            symbols[*i] = *c;
+        }
+
+        let mut edges: BTreeMap<(usize, usize), Vec<char>> = BTreeMap::new();
+        for (from, row) in self.transitions.iter().enumerate() {
+            for (i, targets) in row.iter().enumerate() {
+                for to in targets {
+                    edges.entry((from, *to)).or_default().push(symbols[i]);
                 }
             }
         }
 
-        lambda_closure
+        let mut out = String::new();
+        // Writing to a String cannot fail.
+        dot::write_header(&mut out, "nfa", self.accepting_states.iter().copied()).unwrap();
+        for ((from, to), symbols) in edges {
+            writeln!(
+                out,
+                "    {} -> {} [label=\"{}\"];",
+                from,
+                to,
+                dot::edge_label(&symbols)
+            )
+            .unwrap();
+        }
+        out.push_str("}\n");
+
+        out
+    }
+
+    /// The highest priority (lowest id) token among the states, if any.
+    fn token_of(&self, states: &StateSet) -> Option<usize> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+        states
+            .iter()
+            .filter_map(|s| self.tokens.get(&s))
+            .min()
+            .copied()
+    }
+
+    /// The character index of `ch`, unless it is the lambda character or
+    /// not in the alphabet.
+    pub(crate) fn symbol_index(&self, ch: char) -> Option<usize> {
+        if ch == self.lambda_char {
+            return None;
+        }
+        self.character_map.get(&ch).copied()
+    }
+
+    pub(crate) fn lambda_closures(&self) -> &LambdaClosures {
+        self.closures
+            .get_or_init(|| LambdaClosures::new(&self.transitions))
     }
 
     /*
      * returns the set of NFA states obtained from following character c
      * from a set of states.
      */
-    fn follow_char(&self, states: &StateSet, c: usize) -> StateSet {
-        let mut follow = BTreeSet::new();
+    pub(crate) fn follow_char(&self, states: &StateSet, c: usize) -> StateSet {
+        let mut follow = StateSet::new();
         for state in states.iter() {
-            for transition in self.transitions[*state][c].iter() {
+            for transition in self.transitions[state][c].iter() {
                 follow.insert(*transition);
             }
         }
         follow
     }
 
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
-        let file = File::open(path)?;
+    /// Reads an NFA from a .nfa file. The first token of each row marks
+    /// whether its from state is accepting (`+`) or not (`-`); every row of a
+    /// state must use the same marker. A row without symbols only declares
+    /// the marker, e.g. `+ 5 5` for an accepting state with no way out.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, NfaParseError> {
+        Self::from_file_with(path, ParseMode::Strict)
+    }
+
+    pub fn from_file_with<P: AsRef<Path>, O: Into<ParseOptions>>(
+        path: P,
+        options: O,
+    ) -> Result<Self, NfaParseError> {
+        let path = path.as_ref();
+        let options = options.into();
+        let io_error = |source| NfaParseError::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let file = File::open(path).map_err(io_error)?;
         let reader = BufReader::new(file);
 
-        let mut all_rows = reader.lines().flatten();
-        let first_line = all_rows.next().unwrap();
+        let mut all_rows = reader.lines().enumerate();
+        let first_line = match all_rows.next() {
+            Some((_, line)) => line.map_err(io_error)?,
+            None => return Err(NfaParseError::EmptyFile { path: None }.in_file(path, 1)),
+        };
 
-        let (character_map, lambda_char) = get_char_map(&first_line);
-        let num_states: usize = get_num_states(&first_line);
+        let declared_states: usize = get_num_states(&first_line).map_err(|e| e.in_file(path, 1))?;
+        let (character_map, lambda_char) =
+            get_char_map(&first_line).map_err(|e| e.in_file(path, 1))?;
+        let max_states = if options.auto_size {
+            None
+        } else {
+            Some(declared_states)
+        };
 
-        // let mut rows: Vec<Row> = all_rows.map(|r| r.parse().unwrap()).collect();
         let mut rows: Vec<Row> = Vec::new();
-        for row in all_rows {
-            match Row::from_str_custom(&row) {
-                Ok(row) => rows.push(row),
-                _ => break,
+        let mut row_lines: Vec<usize> = Vec::new();
+        // Acceptance belongs to a state, so every row of a state must agree
+        // on its marker. Remember the line that first set it for diagnostics.
+        let mut markers: BTreeMap<usize, (bool, usize)> = BTreeMap::new();
+        // The first blank line since the last row. Strict mode only allows
+        // blank lines when no row follows them.
+        let mut blank_line = None;
+        for (n, line) in all_rows {
+            let line = line.map_err(io_error)?;
+            let line_number = n + 1;
+            if options.mode == ParseMode::Lenient && is_skippable(&line, &character_map) {
+                debug!("Skipping line {}: {:?}", line_number, line);
+                continue;
             }
-            // rows.push(Row::from_str_custom(&row).unwrap());
+            if line.trim().is_empty() {
+                blank_line = blank_line.or(Some(line_number));
+                continue;
+            }
+            if let Some(blank) = blank_line {
+                return Err(NfaParseError::BlankLine {
+                    at: Location::new(blank, 1),
+                }
+                .in_file(path, blank));
+            }
+
+            let row = Row::from_str_custom(&line).map_err(|e| e.in_file(path, line_number))?;
+            check_row(&line, &row, &character_map, max_states)
+                .map_err(|e| e.in_file(path, line_number))?;
+
+            let accepting = row.get_accepting_state();
+            match markers.get(&row.get_from_id()) {
+                Some(&(marker, first_line)) if marker != accepting => {
+                    return Err(NfaParseError::ConflictingMarker {
+                        at: Location::new(line_number, 1),
+                        state: row.get_from_id(),
+                        first_line,
+                    }
+                    .in_file(path, line_number));
+                }
+                Some(_) => {}
+                None => {
+                    markers.insert(row.get_from_id(), (accepting, line_number));
+                }
+            }
+
+            rows.push(row);
+            row_lines.push(line_number);
         }
 
-        make_indexable(&mut rows);
+        let state_map = make_indexable(&mut rows);
+        let num_states = state_map.len();
+        if num_states != declared_states {
+            if options.auto_size {
+                warn!(
+                    "{}: header declares {} states but {} are used",
+                    path.display(),
+                    declared_states,
+                    num_states
+                );
+            } else {
+                let column = tokenize(&first_line).next().map_or(1, |(c, _)| c);
+                return Err(NfaParseError::StateCountMismatch {
+                    at: Location::new(1, column),
+                    declared: declared_states,
+                    found: num_states,
+                }
+                .in_file(path, 1));
+            }
+        }
 
-        let accepting_state_from_ids: Vec<usize> = rows
+        let accepting_states: BTreeSet<usize> = markers
             .iter()
-            .filter(|r| r.get_accepting_state())
-            .map(|r| r.get_from_id().to_owned())
+            .filter(|(_, (accepting, _))| *accepting)
+            .map(|(id, _)| state_map[id])
             .collect();
 
         let transitions: Vec<Vec<Vec<usize>>> = get_transitions(&rows, &character_map, num_states);
 
+        let mut lines = vec![Vec::new(); num_states];
+        for (row, line) in rows.iter().zip(row_lines) {
+            lines[row.get_from_id()].push(line);
+        }
+
         Ok(Self {
             lambda_char,
             transitions,
             character_map,
-            accepting_states: BTreeSet::from_iter(accepting_state_from_ids),
+            accepting_states,
+            tokens: BTreeMap::new(),
+            lines,
+            closures: OnceLock::new(),
         })
     }
 }
 
-fn make_indexable(rows: &mut Vec<Row>) {
+/// The lambda closure of every state, computed once per NFA.
+///
+/// States on a lambda cycle share their closure, so the lambda graph is
+/// condensed into strongly connected components with Tarjan's algorithm.
+/// Tarjan finishes a component only after every component it reaches, so
+/// each closure is its members plus the already known closures of its
+/// successors.
+#[derive(Debug, Clone)]
+pub(crate) struct LambdaClosures {
+    // state => component
+    component: Vec<usize>,
+    // component => closure
+    closures: Vec<StateSet>,
+}
+
+impl LambdaClosures {
+    fn new(transitions: &[Vec<Vec<usize>>]) -> Self {
+        const UNVISITED: usize = usize::MAX;
+        let n = transitions.len();
+        let lambda = |state: usize| -> &[usize] { &transitions[state][0] };
+
+        let mut index = vec![UNVISITED; n];
+        let mut low = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut component = vec![UNVISITED; n];
+        let mut closures: Vec<StateSet> = Vec::new();
+        let mut next_index = 0;
+
+        for root in 0..n {
+            if index[root] != UNVISITED {
+                continue;
+            }
+
+            // An explicit call stack of (state, next lambda edge to try), so
+            // long lambda chains cannot overflow the thread's stack.
+            index[root] = next_index;
+            low[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            let mut calls = vec![(root, 0)];
+
+            while let Some((v, edge)) = calls.last_mut() {
+                let v = *v;
+                if let Some(&w) = lambda(v).get(*edge) {
+                    *edge += 1;
+                    if index[w] == UNVISITED {
+                        index[w] = next_index;
+                        low[w] = next_index;
+                        next_index += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        calls.push((w, 0));
+                    } else if on_stack[w] {
+                        low[v] = low[v].min(index[w]);
+                    }
+                    continue;
+                }
+
+                calls.pop();
+                if let Some((parent, _)) = calls.last() {
+                    low[*parent] = low[*parent].min(low[v]);
+                }
+                if low[v] != index[v] {
+                    continue;
+                }
+
+                let id = closures.len();
+                let mut closure = StateSet::new();
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    component[w] = id;
+                    closure.insert(w);
+                    if w == v {
+                        break;
+                    }
+                }
+
+                let members: Vec<usize> = closure.iter().collect();
+                for member in members {
+                    for target in lambda(member) {
+                        // Targets already in the closure add nothing new.
+                        if !closure.contains(*target) {
+                            closure.union_with(&closures[component[*target]]);
+                        }
+                    }
+                }
+                closures.push(closure);
+            }
+        }
+
+        Self {
+            component,
+            closures,
+        }
+    }
+
+    pub(crate) fn of(&self, state: usize) -> &StateSet {
+        &self.closures[self.component[state]]
+    }
+
+    /// The set of NFA states reached from `states` by following only
+    /// lambda transitions.
+    pub(crate) fn follow(&self, states: &StateSet) -> StateSet {
+        let mut closure = StateSet::new();
+        for state in states.iter() {
+            // A state already in the result brought its whole closure along.
+            if !closure.contains(state) {
+                closure.union_with(self.of(state));
+            }
+        }
+        closure
+    }
+}
+
+/// Picks a lambda character that is not one of `symbols`, trying `preferred`
+/// first, then `regex::DEFAULT_LAMBDA`, then the private use area.
+pub(crate) fn fresh_lambda(symbols: &BTreeSet<char>, preferred: char) -> char {
+    std::iter::once(preferred)
+        .chain(std::iter::once(DEFAULT_LAMBDA))
+        .chain((0xE000..=0xF8FF).filter_map(std::char::from_u32))
+        .find(|c| !symbols.contains(c))
+        .expect("an alphabet cannot use every private-use character")
+}
+
+fn is_skippable(line: &str, char_map: &BTreeMap<char, usize>) -> bool {
+    match line.trim_start().chars().next() {
+        None => true,
+        Some('#') => !char_map.contains_key(&'#'),
+        Some(_) => false,
+    }
+}
+
+/// Checks a parsed row against the header: both ids must be below the
+/// declared state count, if given, and every symbol must be in the alphabet.
+fn check_row(
+    line: &str,
+    row: &Row,
+    char_map: &BTreeMap<char, usize>,
+    max_states: Option<usize>,
+) -> Result<(), NfaParseError> {
+    let tokens: Vec<(usize, &str)> = tokenize(line).collect();
+
+    let ids = [row.get_from_id(), row.get_to_id()];
+    for (&(column, _), id) in tokens[1..3].iter().zip(&ids) {
+        let num_states = match max_states {
+            Some(n) => n,
+            None => break,
+        };
+        if *id >= num_states {
+            return Err(NfaParseError::StateOutOfRange {
+                at: Location::new(1, column),
+                id: *id,
+                num_states,
+            });
+        }
+    }
+
+    for (&(column, token), c) in tokens[3..].iter().zip(row.get_transitions()) {
+        if !char_map.contains_key(c) {
+            return Err(NfaParseError::UnknownSymbol {
+                at: Location::new(1, column),
+                symbol: token.to_owned(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Renumbers states densely in order of appearance, keeping the start state
+/// at 0. Returns the mapping from file ids to the new indices.
+fn make_indexable(rows: &mut [Row]) -> BTreeMap<usize, usize> {
     let mut state_map: BTreeMap<usize, usize> = BTreeMap::new();
     state_map.insert(0, 0); // Start node is ALWAYS 0
 
@@ -195,6 +745,8 @@ fn make_indexable(rows: &mut Vec<Row>) {
         row.set_from_id(*from_index);
         row.set_to_id(*to_index);
     }
+
+    state_map
 }
 
 fn get_transitions(
@@ -213,27 +765,400 @@ fn get_transitions(
     }
     outer
 }
-fn get_num_states(first_lines: &str) -> usize {
-    first_lines
-        .split(' ')
-        .collect::<Vec<&str>>()
-        .into_iter()
-        .next()
-        .unwrap()
-        .parse()
-        .unwrap()
+fn get_num_states(first_line: &str) -> Result<usize, NfaParseError> {
+    let (column, token) =
+        tokenize(first_line)
+            .next()
+            .ok_or_else(|| NfaParseError::MissingStateCount {
+                at: Location::new(1, 1),
+            })?;
+
+    token.parse().map_err(|_| NfaParseError::BadStateCount {
+        at: Location::new(1, column),
+        token: token.to_owned(),
+    })
 }
-fn get_char_map(first_line: &str) -> (BTreeMap<char, usize>, char) {
-    let alphabet_letters: Vec<&str> = first_line
-        .split(' ')
+
+fn get_char_map(first_line: &str) -> Result<(BTreeMap<char, usize>, char), NfaParseError> {
+    let alphabet_letters: Vec<(usize, &str)> = tokenize(first_line)
         .skip(1) // skip num of states
         .collect();
-    let lambda_char = alphabet_letters[0].parse().unwrap();
+
     let mut map = BTreeMap::new();
+    for (i, &(column, v)) in alphabet_letters.iter().enumerate() {
+        let at = Location::new(1, column);
+        let c = v.parse().map_err(|_| NfaParseError::MultiCharSymbol {
+            at: at.clone(),
+            token: v.to_owned(),
+        })?;
+        // Every symbol, the lambda character included, names one column.
+        if map.insert(c, i).is_some() {
+            return Err(NfaParseError::RepeatedSymbol { at, symbol: c });
+        }
+    }
+
+    match alphabet_letters.first() {
+        Some((_, lambda)) => Ok((map, lambda.parse().unwrap())),
+        None => Err(NfaParseError::MissingLambda {
+            at: Location::new(1, first_line.chars().count() + 1),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use dfa_optimizer::test_util::{strings, Rng};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn write_nfa(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("nfamatch-{}-{}.nfa", std::process::id(), name));
+        File::create(&path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_strict_rejects_blank_line() {
+        let path = write_nfa("strict", "3 L a b\n- 0 1 a\n\n+ 1 2 b\n");
+        match Nfa::from_file(&path).unwrap_err() {
+            NfaParseError::BlankLine { at } => assert_eq!((at.line, at.column), (3, 1)),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_strict_allows_blank_lines_at_end() {
+        let path = write_nfa("trailing", "3 L a b\n- 0 1 a\n+ 1 2 b\n\n  \n");
+        let nfa = Nfa::from_file(&path).unwrap();
+        assert_eq!(nfa.transitions[1][2], vec![2]);
+    }
+
+    #[test]
+    fn test_lenient_skips_blank_and_comment_lines() {
+        let path = write_nfa(
+            "lenient",
+            "3 L a b\n# first row\n- 0 1 a\n\n  # second row\n+ 1 2 b\n",
+        );
+        let nfa = Nfa::from_file_with(&path, ParseMode::Lenient).unwrap();
+        assert_eq!(nfa.transitions[1][2], vec![2]);
+    }
+
+    #[test]
+    fn test_lenient_keeps_hash_rows_in_alphabet() {
+        // With `#` in the alphabet the row is parsed, and `#` is no marker.
+        let path = write_nfa("hash", "2 L #\n# 0 1 #\n");
+        match Nfa::from_file_with(&path, ParseMode::Lenient).unwrap_err() {
+            NfaParseError::BadMarker { at, token } => {
+                assert_eq!((at.line, at.column), (2, 1));
+                assert_eq!(token, "#");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
 
-    for (i, v) in alphabet_letters.iter().enumerate() {
-        map.insert(v.parse().expect("Error while looking at alphabet"), i);
+    #[test]
+    fn test_conflicting_markers() {
+        let path = write_nfa("conflict", "3 L a\n- 0 1 a\n- 1 2 a\n+ 1 1 a\n");
+        match Nfa::from_file(&path).unwrap_err() {
+            NfaParseError::ConflictingMarker {
+                at,
+                state,
+                first_line,
+            } => {
+                assert_eq!(at.line, 4);
+                assert_eq!(state, 1);
+                assert_eq!(first_line, 3);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_accepting_state_without_transitions() {
+        // States are renumbered in order of appearance, so 2 becomes 1 and
+        // the accepting state 1 becomes 2.
+        let path = write_nfa("sink", "3 L a\n- 0 2 a\n- 2 1 a\n+ 1 1\n");
+        let nfa = Nfa::from_file(&path).unwrap();
+        assert_eq!(nfa.num_states(), 3);
+        assert!(!nfa.is_accepting(1));
+        assert!(nfa.is_accepting(2));
+        assert!(nfa.transitions[2].iter().all(|t| t.is_empty()));
+    }
+
+    #[test]
+    fn test_state_count_mismatch() {
+        let path = write_nfa("count", "5 L a\n- 0 1 a\n+ 1 2 a\n");
+        match Nfa::from_file(&path).unwrap_err() {
+            NfaParseError::StateCountMismatch {
+                at,
+                declared,
+                found,
+            } => {
+                assert_eq!((at.line, at.column), (1, 1));
+                assert_eq!(declared, 5);
+                assert_eq!(found, 3);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_auto_size() {
+        let path = write_nfa("autosize", "2 L a\n- 0 1 a\n- 1 20 a\n+ 20 20\n");
+        let options = ParseOptions {
+            auto_size: true,
+            ..Default::default()
+        };
+        let nfa = Nfa::from_file_with(&path, options).unwrap();
+        assert_eq!(nfa.num_states(), 3);
+        assert!(nfa.is_accepting(2));
+    }
+
+    #[test]
+    fn test_multi_byte_alphabet() {
+        let path = write_nfa("greek", "3 λ α β 🦀\n- 0 1 α\n- 1 1 β\n- 1 2 🦀\n+ 2 2\n");
+        let nfa = Nfa::from_file(&path).unwrap();
+        let mut table = nfa.to_dfa();
+        table.optimize();
+        assert_eq!(table.alphabet().symbols(), &['α', 'β', '🦀']);
+
+        assert!(table.does_match("αβ🦀").is_accepted());
+        let pos = table.does_match("αβ🦀🦀").failed_at().unwrap();
+        assert_eq!((pos.char_index, pos.byte_offset), (3, 8));
+    }
+
+    #[test]
+    fn test_repeated_header_symbol() {
+        for (name, header, column) in &[("dup", "2 L a a", 7), ("duplambda", "2 L a L", 7)] {
+            let path = write_nfa(name, &format!("{}\n- 0 1 a\n+ 1 1\n", header));
+            match Nfa::from_file(&path).unwrap_err() {
+                NfaParseError::RepeatedSymbol { at, symbol } => {
+                    assert_eq!((at.line, at.column), (1, *column));
+                    assert_eq!(symbol.to_string(), header[column - 1..]);
+                }
+                e => panic!("unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_errors() {
+        let error = |name, header| {
+            let path = write_nfa(name, &format!("{}\n- 0 1 a\n+ 1 1\n", header));
+            Nfa::from_file(&path).unwrap_err()
+        };
+        assert!(matches!(
+            error("blank_header", ""),
+            NfaParseError::MissingStateCount { .. }
+        ));
+        assert!(matches!(
+            error("no_lambda", "2"),
+            NfaParseError::MissingLambda { at } if at.column == 2
+        ));
+        assert!(matches!(
+            error("long_symbol", "2 L ab"),
+            NfaParseError::MultiCharSymbol { at, token } if at.column == 5 && token == "ab"
+        ));
+    }
+
+    #[test]
+    fn test_empty_language_and_empty_string() {
+        let empty = Nfa::new('L');
+        let mut table = empty.to_dfa();
+        table.optimize();
+        assert_eq!(table.rows().len(), 1);
+        assert!(table.is_empty_language());
+        assert!(!table.accepts_empty_string());
+
+        let path = write_nfa("epsilon", "2 L a\n+ 0 1 a\n- 1 1\n");
+        let mut table = Nfa::from_file(&path).unwrap().to_dfa();
+        table.optimize();
+        assert!(table.accepts_empty_string());
+        assert!(table.does_match("").is_accepted());
     }
 
-    (map, lambda_char)
+    #[test]
+    fn test_to_dot() {
+        let path = write_nfa("dot", "3 L a b c d\n- 0 1 a b c\n- 0 2 d L\n+ 1 1\n+ 2 2\n");
+        let dot = Nfa::from_file(&path).unwrap().to_dot();
+        assert!(dot.starts_with("digraph nfa {\n"));
+        assert!(dot.contains("    1 [shape=doublecircle];\n"));
+        assert!(dot.contains("    0 -> 1 [label=\"a-c\"];\n"));
+        assert!(dot.contains("    0 -> 2 [label=\"L,d\"];\n"));
+    }
+
+    #[test]
+    fn test_provenance() {
+        // a(b|c) with a lambda detour, state 3 reached from 1 on lambda.
+        let path = write_nfa(
+            "provenance",
+            "5 L a b c\n- 0 1 a\n- 1 2 b\n- 1 3 L\n- 3 4 c\n+ 2 2\n+ 4 4\n",
+        );
+        let nfa = Nfa::from_file(&path).unwrap();
+        assert_eq!(nfa.source_lines(1), &[3, 4]);
+        assert_eq!(nfa.source_lines(4), &[7]);
+        assert!(nfa.source_lines(9).is_empty());
+
+        let (mut table, provenance) = nfa.to_dfa_with_provenance();
+        assert_eq!(provenance.len(), table.rows().len());
+        assert_eq!(provenance[0], StateSet::from_iter(vec![0]));
+        for (row, states) in provenance.iter().enumerate() {
+            let accepts = states.iter().any(|s| nfa.is_accepting(s));
+            assert_eq!(table[row].is_accepting(), accepts);
+        }
+        let after_a = table[0][0].unwrap();
+        assert_eq!(provenance[after_a], StateSet::from_iter(vec![1, 3]));
+
+        // The two accepting rows are equivalent and get merged.
+        let merges = table.optimize();
+        assert_eq!(table.rows().len(), 3);
+        let accepting = &merges[2];
+        assert_eq!(accepting.len(), 2);
+        assert!(accepting
+            .iter()
+            .all(|row| provenance[*row].iter().any(|s| nfa.is_accepting(s))));
+    }
+
+    /// All strings over `symbols` up to `max_len` characters, followed by
+    /// `count` longer pseudo-random ones.
+    fn sample_inputs(symbols: &[char], max_len: usize, count: usize) -> Vec<String> {
+        let mut inputs = strings(symbols, max_len);
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..count {
+            let len = max_len + 1 + rng.below(10);
+            let input = (0..len).map(|_| symbols[rng.below(symbols.len())]);
+            inputs.push(input.collect());
+        }
+        inputs
+    }
+
+    #[test]
+    fn test_matches_agrees_with_to_dfa() {
+        let mut nfas = Vec::new();
+        for name in &["float", "3_id", "cblock"] {
+            let path = format!("{}/../{}.nfa", env!("CARGO_MANIFEST_DIR"), name);
+            nfas.push(Nfa::from_file(&path).unwrap());
+        }
+        for pattern in &["a(b|c)*d+e?", "(a|b)*a(a|b)(a|b)", "(ab|a)*(b|())", "()"] {
+            nfas.push(crate::regex::compile(pattern).unwrap());
+        }
+
+        for nfa in &nfas {
+            let table = nfa.to_dfa();
+            let mut optimized = nfa.to_dfa();
+            optimized.optimize();
+
+            // The alphabet plus the lambda character and a foreign symbol.
+            let mut symbols: Vec<char> = nfa.character_map().keys().copied().collect();
+            symbols.push('~');
+            let max_len = if symbols.len() > 20 { 2 } else { 4 };
+            for input in sample_inputs(&symbols, max_len, 2000) {
+                let expected = nfa.matches(&input);
+                assert_eq!(
+                    table.does_match(&input).is_accepted(),
+                    expected,
+                    "{:?}",
+                    input
+                );
+                assert_eq!(
+                    optimized.does_match(&input).is_accepted(),
+                    expected,
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_without_determinizing() {
+        // The DFA for this needs a row for each of the 2^21 possible
+        // suffixes, but simulating the NFA stays cheap.
+        let pattern = format!("(a|b)*a{}", "(a|b)".repeat(20));
+        let nfa = crate::regex::compile(&pattern).unwrap();
+        let tail = "b".repeat(20);
+        assert!(nfa.matches(&format!("abba{}", tail)));
+        assert!(nfa.matches(&format!("a{}", tail)));
+        assert!(!nfa.matches(&format!("ab{}", tail)));
+        assert!(!nfa.matches(&tail));
+        // Every call above shares one set of closures.
+        assert!(std::ptr::eq(nfa.lambda_closures(), nfa.lambda_closures()));
+
+        let path = write_nfa("lambda_input", "2 L a\n- 0 1 L\n+ 1 1\n");
+        let nfa = Nfa::from_file(&path).unwrap();
+        assert!(nfa.matches(""));
+        assert!(!nfa.matches("L"));
+        assert!(!nfa.matches("a"));
+    }
+
+    #[test]
+    fn test_limits() {
+        // The 11th symbol from the end, which needs a state for each of the
+        // 2^11 possible last 11 symbols.
+        let pattern = format!("(a|b)*a{}", "(a|b)".repeat(10));
+        let nfa = crate::regex::compile(&pattern).unwrap();
+        let full = nfa.to_dfa();
+        let needed = full.rows().len();
+        assert!(needed >= 1 << 11);
+
+        let table = nfa
+            .to_dfa_with_limits(Some(needed), Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(table.rows(), full.rows());
+
+        assert!(matches!(
+            nfa.to_dfa_with_limits(Some(100), None),
+            Err(DeterminizeError::TooManyStates { reached: 100 })
+        ));
+        assert!(matches!(
+            nfa.to_dfa_with_limits(Some(0), None),
+            Err(DeterminizeError::TooManyStates { reached: 0 })
+        ));
+        assert!(matches!(
+            nfa.to_dfa_with_limits(None, Some(Duration::from_secs(0))),
+            Err(DeterminizeError::TookTooLong { .. })
+        ));
+    }
+
+    /// Lambda closure by plain graph search, to check `LambdaClosures`.
+    fn naive_closure(nfa: &Nfa, state: usize) -> StateSet {
+        let mut closure = StateSet::new();
+        let mut stack = vec![state];
+        while let Some(s) = stack.pop() {
+            if closure.insert(s) {
+                stack.extend(nfa.transitions[s][0].iter().copied());
+            }
+        }
+        closure
+    }
+
+    #[test]
+    fn test_lambda_closures() {
+        // Two lambda cycles, 1 <-> 2 and 3 -> 4 -> 5 -> 3, joined by 2 -> 3.
+        let path = write_nfa(
+            "closures",
+            "7 L a\n- 0 1 L\n- 1 2 L\n- 2 1 L\n- 2 3 L\n- 3 4 L\n- 4 5 L\n- 5 3 L\n- 5 6 a\n+ 6 6\n",
+        );
+        let mut nfas = vec![Nfa::from_file(&path).unwrap()];
+        for name in &["float", "3_id", "cblock"] {
+            let path = format!("{}/../{}.nfa", env!("CARGO_MANIFEST_DIR"), name);
+            nfas.push(Nfa::from_file(&path).unwrap());
+        }
+
+        for nfa in &nfas {
+            let closures = LambdaClosures::new(&nfa.transitions);
+            for state in 0..nfa.num_states() {
+                assert_eq!(closures.of(state), &naive_closure(nfa, state));
+            }
+        }
+
+        let closures = LambdaClosures::new(&nfas[0].transitions);
+        assert_eq!(closures.of(1), closures.of(2));
+        assert_eq!(closures.of(0), &StateSet::from_iter(0..6));
+    }
 }
@@ -0,0 +1,441 @@
+//! A small regular-expression front end that compiles to an `Nfa` by
+//! Thompson construction.
+//!
+//! The dialect supports concatenation, alternation `|`, the postfix
+//! operators `*`, `+` and `?`, grouping with `(...)`, character classes such
+//! as `[a-z_]`, and escapes `\x` for any special character (`\n`, `\t` and
+//! `\r` mean the usual control characters). The alphabet of the resulting
+//! NFA is exactly the set of symbols the pattern mentions.
+
+use crate::builder::NfaBuilder;
+use crate::error::RegexError;
+use crate::nfa::{fresh_lambda, Nfa};
+use std::collections::BTreeSet;
+
+/// The lambda character used unless the pattern already contains it.
+pub const DEFAULT_LAMBDA: char = 'λ';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ast {
+    Empty,
+    /// Any one of the symbols.
+    Class(BTreeSet<char>),
+    Concat(Vec<Ast>),
+    Alternate(Vec<Ast>),
+    Star(Box<Ast>),
+    Plus(Box<Ast>),
+    Optional(Box<Ast>),
+}
+
+/// Compiles a pattern into an NFA, choosing a lambda character that does not
+/// clash with the pattern's symbols.
+pub fn compile(pattern: &str) -> Result<Nfa, RegexError> {
+    let ast = Parser::new(pattern).parse()?;
+    let mut symbols = BTreeSet::new();
+    collect_symbols(&ast, &mut symbols);
+
+    let lambda_char = fresh_lambda(&symbols, DEFAULT_LAMBDA);
+
+    Ok(build(&ast, lambda_char, symbols))
+}
+
+/// Compiles a pattern into an NFA using the given lambda character, which
+/// must not appear in the pattern.
+pub fn compile_with_lambda(pattern: &str, lambda_char: char) -> Result<Nfa, RegexError> {
+    let ast = Parser::new(pattern).parse()?;
+    let mut symbols = BTreeSet::new();
+    collect_symbols(&ast, &mut symbols);
+
+    if symbols.contains(&lambda_char) {
+        return Err(RegexError::LambdaInPattern { lambda_char });
+    }
+
+    Ok(build(&ast, lambda_char, symbols))
+}
+
+fn collect_symbols(ast: &Ast, symbols: &mut BTreeSet<char>) {
+    match ast {
+        Ast::Empty => {}
+        Ast::Class(class) => symbols.extend(class.iter().copied()),
+        Ast::Concat(parts) | Ast::Alternate(parts) => {
+            for part in parts {
+                collect_symbols(part, symbols);
+            }
+        }
+        Ast::Star(inner) | Ast::Plus(inner) | Ast::Optional(inner) => {
+            collect_symbols(inner, symbols)
+        }
+    }
+}
+
+fn build(ast: &Ast, lambda_char: char, symbols: BTreeSet<char>) -> Nfa {
+    let mut thompson = Thompson {
+        lambda_char,
+        num_states: 0,
+        edges: Vec::new(),
+    };
+    // The start state has to be state 0, so allocate it before the fragment.
+    let start = thompson.new_state();
+    let (first, last) = thompson.fragment(ast);
+    thompson.lambda(start, first);
+
+    let mut builder = NfaBuilder::new(lambda_char)
+        .alphabet(symbols)
+        .add_states(thompson.num_states)
+        .mark_accepting(last);
+    for (from, to, symbols) in thompson.edges {
+        builder = builder.add_transition(from, to, symbols);
+    }
+
+    builder
+        .build()
+        .expect("Thompson construction only uses its own states and symbols")
+}
+
+struct Thompson {
+    lambda_char: char,
+    num_states: usize,
+    edges: Vec<(usize, usize, Vec<char>)>,
+}
+
+impl Thompson {
+    fn new_state(&mut self) -> usize {
+        self.num_states += 1;
+        self.num_states - 1
+    }
+
+    fn lambda(&mut self, from: usize, to: usize) {
+        self.edges.push((from, to, vec![self.lambda_char]));
+    }
+
+    /// Builds the fragment for `ast` and returns its entry and exit states.
+    fn fragment(&mut self, ast: &Ast) -> (usize, usize) {
+        match ast {
+            Ast::Empty => {
+                let state = self.new_state();
+                (state, state)
+            }
+            Ast::Class(class) => {
+                let (start, end) = (self.new_state(), self.new_state());
+                self.edges
+                    .push((start, end, class.iter().copied().collect()));
+                (start, end)
+            }
+            Ast::Concat(parts) => {
+                let mut fragments = parts
+                    .iter()
+                    .map(|part| self.fragment(part))
+                    .collect::<Vec<_>>();
+                for pair in fragments.windows(2) {
+                    self.lambda(pair[0].1, pair[1].0);
+                }
+                let start = fragments.first().map(|f| f.0);
+                let end = fragments.pop().map(|f| f.1);
+                match (start, end) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => self.fragment(&Ast::Empty),
+                }
+            }
+            Ast::Alternate(parts) => {
+                let (start, end) = (self.new_state(), self.new_state());
+                for part in parts {
+                    let (first, last) = self.fragment(part);
+                    self.lambda(start, first);
+                    self.lambda(last, end);
+                }
+                (start, end)
+            }
+            Ast::Star(inner) => {
+                let (start, end) = (self.new_state(), self.new_state());
+                let (first, last) = self.fragment(inner);
+                self.lambda(start, first);
+                self.lambda(last, first);
+                self.lambda(last, end);
+                self.lambda(start, end);
+                (start, end)
+            }
+            Ast::Plus(inner) => {
+                let (start, end) = (self.new_state(), self.new_state());
+                let (first, last) = self.fragment(inner);
+                self.lambda(start, first);
+                self.lambda(last, first);
+                self.lambda(last, end);
+                (start, end)
+            }
+            Ast::Optional(inner) => {
+                let (start, end) = (self.new_state(), self.new_state());
+                let (first, last) = self.fragment(inner);
+                self.lambda(start, first);
+                self.lambda(last, end);
+                self.lambda(start, end);
+                (start, end)
+            }
+        }
+    }
+}
+
+/// Recursive descent parser. Positions in errors are 0-based char indices.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(pattern: &str) -> Self {
+        Self {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn parse(mut self) -> Result<Ast, RegexError> {
+        let ast = self.alternate()?;
+        match self.peek() {
+            None => Ok(ast),
+            Some(_) => Err(RegexError::UnmatchedParen { pos: self.pos }),
+        }
+    }
+
+    // alternate := concat ('|' concat)*
+    fn alternate(&mut self) -> Result<Ast, RegexError> {
+        let mut branches = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concat()?);
+        }
+
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Ast::Alternate(branches)
+        })
+    }
+
+    // concat := repeat*
+    fn concat(&mut self) -> Result<Ast, RegexError> {
+        let mut parts = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            parts.push(self.repeat()?);
+        }
+
+        Ok(match parts.len() {
+            0 => Ast::Empty,
+            1 => parts.pop().unwrap(),
+            _ => Ast::Concat(parts),
+        })
+    }
+
+    // repeat := atom ('*' | '+' | '?')*
+    fn repeat(&mut self) -> Result<Ast, RegexError> {
+        let mut ast = self.atom()?;
+        loop {
+            ast = match self.peek() {
+                Some('*') => Ast::Star(Box::new(ast)),
+                Some('+') => Ast::Plus(Box::new(ast)),
+                Some('?') => Ast::Optional(Box::new(ast)),
+                _ => return Ok(ast),
+            };
+            self.pos += 1;
+        }
+    }
+
+    // atom := '(' alternate ')' | '[' class ']' | escape | literal
+    fn atom(&mut self) -> Result<Ast, RegexError> {
+        let pos = self.pos;
+        match self.next() {
+            Some('(') => {
+                let ast = self.alternate()?;
+                match self.next() {
+                    Some(')') => Ok(ast),
+                    _ => Err(RegexError::UnmatchedParen { pos }),
+                }
+            }
+            Some('[') => self.class(pos),
+            Some('\\') => Ok(single(self.escape()?)),
+            Some(c @ '*') | Some(c @ '+') | Some(c @ '?') => {
+                Err(RegexError::NothingToRepeat { pos, op: c })
+            }
+            Some(c) => Ok(single(c)),
+            None => Err(RegexError::UnexpectedEnd { pos }),
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, RegexError> {
+        match self.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some(c) => Ok(c),
+            None => Err(RegexError::UnexpectedEnd { pos: self.pos - 1 }),
+        }
+    }
+
+    fn class_char(&mut self) -> Result<char, RegexError> {
+        match self.next() {
+            Some('\\') => self.escape(),
+            Some(c) => Ok(c),
+            None => Err(RegexError::UnexpectedEnd { pos: self.pos - 1 }),
+        }
+    }
+
+    // class := '^'? (c | c '-' c)* ']'
+    fn class(&mut self, open: usize) -> Result<Ast, RegexError> {
+        if self.peek() == Some('^') {
+            return Err(RegexError::NegatedClass { pos: open });
+        }
+
+        let mut class = BTreeSet::new();
+        loop {
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    break;
+                }
+                None => return Err(RegexError::UnclosedClass { pos: open }),
+                Some(_) => {}
+            }
+
+            let start_pos = self.pos;
+            let low = self.class_char()?;
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']');
+            if is_range {
+                self.pos += 1;
+                let high = self.class_char()?;
+                if high < low {
+                    return Err(RegexError::BadRange {
+                        pos: start_pos,
+                        low,
+                        high,
+                    });
+                }
+                class.extend(low..=high);
+            } else {
+                class.insert(low);
+            }
+        }
+
+        if class.is_empty() {
+            return Err(RegexError::EmptyClass { pos: open });
+        }
+        Ok(Ast::Class(class))
+    }
+}
+
+fn single(c: char) -> Ast {
+    let mut class = BTreeSet::new();
+    class.insert(c);
+    Ast::Class(class)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(nfa: &Nfa, input: &str) -> bool {
+        let mut table = nfa.to_dfa();
+        table.optimize();
+        table.does_match(input).is_accepted()
+    }
+
+    #[test]
+    fn test_operators() {
+        let nfa = compile("a(b|c)*d+e?").unwrap();
+        assert!(matches(&nfa, "ad"));
+        assert!(matches(&nfa, "abcbddde"));
+        assert!(!matches(&nfa, "a"));
+        assert!(!matches(&nfa, "adee"));
+        assert!(!matches(&nfa, "bd"));
+    }
+
+    #[test]
+    fn test_classes_and_escapes() {
+        let nfa = compile(r"[a-zA-Z_][a-z0-9_-]*\*").unwrap();
+        assert!(matches(&nfa, "_x-1*"));
+        assert!(matches(&nfa, "A*"));
+        assert!(!matches(&nfa, "1a*"));
+        assert!(!matches(&nfa, "ab"));
+    }
+
+    #[test]
+    fn test_whitespace_symbols_round_trip() {
+        let table = compile("a[ \t]b").unwrap().to_dfa();
+        let mut text = Vec::new();
+        table.write_to(&mut text).unwrap();
+        let reread = dfa_optimizer::Table::from_reader(text.as_slice()).unwrap();
+        assert_eq!(reread.rows(), table.rows());
+        assert_eq!(reread.alphabet(), table.alphabet());
+        assert!(reread.does_match("a b").is_accepted());
+        assert!(reread.does_match("a\tb").is_accepted());
+    }
+
+    #[test]
+    fn test_empty_pattern() {
+        let nfa = compile("()").unwrap();
+        assert!(nfa.is_accepting(1));
+        assert_eq!(nfa.character_map().len(), 1);
+    }
+
+    #[test]
+    fn test_lambda_choice() {
+        assert_eq!(compile("ab").unwrap().lambda_char(), DEFAULT_LAMBDA);
+        assert_ne!(compile("λ").unwrap().lambda_char(), DEFAULT_LAMBDA);
+        assert_eq!(
+            compile_with_lambda("a", 'a').unwrap_err(),
+            RegexError::LambdaInPattern { lambda_char: 'a' }
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            compile("(ab").unwrap_err(),
+            RegexError::UnmatchedParen { pos: 0 }
+        );
+        assert_eq!(
+            compile("ab)").unwrap_err(),
+            RegexError::UnmatchedParen { pos: 2 }
+        );
+        assert_eq!(
+            compile("a|*").unwrap_err(),
+            RegexError::NothingToRepeat { pos: 2, op: '*' }
+        );
+        assert_eq!(
+            compile("[z-a]").unwrap_err(),
+            RegexError::BadRange {
+                pos: 1,
+                low: 'z',
+                high: 'a'
+            }
+        );
+        assert_eq!(
+            compile("[^a]").unwrap_err(),
+            RegexError::NegatedClass { pos: 0 }
+        );
+        assert_eq!(
+            compile("[ab").unwrap_err(),
+            RegexError::UnclosedClass { pos: 0 }
+        );
+        assert_eq!(
+            compile("[]").unwrap_err(),
+            RegexError::EmptyClass { pos: 0 }
+        );
+        assert_eq!(
+            compile("a\\").unwrap_err(),
+            RegexError::UnexpectedEnd { pos: 2 }
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::error::{Location, NfaParseError};
+use dfa_optimizer::row::tokenize;
 use log::*;
 
 #[derive(Debug, Default)]
@@ -42,28 +44,56 @@ impl Row {
         &self.transitions
     }
 
-    // type Err = ();
-    pub fn from_str_custom(input: &str) -> Result<Self, ()> {
+    pub fn from_str_custom(input: &str) -> Result<Self, NfaParseError> {
         info!("Input for from_str_custom {}", input);
-        let tokens: Vec<&str> = input.trim().split_whitespace().collect();
+        let tokens: Vec<(usize, &str)> = tokenize(input).collect();
 
         match tokens.as_slice() {
-            [accept, from_id, to_id, transitions @ ..] => {
-                let is_accept = *accept == "+";
-                let from_id = from_id.parse().unwrap();
-                let to_id = to_id.parse().unwrap();
-                let transitions: Vec<char> =
-                    transitions.iter().map(|s| s.parse().unwrap()).collect();
+            [(column, accept), from_id, to_id, transitions @ ..] => {
+                let is_accept = match *accept {
+                    "+" => true,
+                    "-" => false,
+                    _ => {
+                        return Err(NfaParseError::BadMarker {
+                            at: Location::new(1, *column),
+                            token: (*accept).to_owned(),
+                        })
+                    }
+                };
+                let from_id = parse_id(*from_id)?;
+                let to_id = parse_id(*to_id)?;
+                let transitions = transitions
+                    .iter()
+                    .map(|&(column, s)| parse_symbol(column, s))
+                    .collect::<Result<Vec<char>, _>>()?;
 
                 Ok(Row::new(is_accept, from_id, to_id, transitions))
             }
-            _ => Err(()),
+            _ => Err(NfaParseError::ShortRow {
+                at: Location::new(1, input.chars().count() + 1),
+                fields: tokens.len(),
+            }),
         }
     }
 }
 
+fn parse_id((column, token): (usize, &str)) -> Result<usize, NfaParseError> {
+    token.parse().map_err(|_| NfaParseError::NonNumericId {
+        at: Location::new(1, column),
+        token: token.to_owned(),
+    })
+}
+
+fn parse_symbol(column: usize, token: &str) -> Result<char, NfaParseError> {
+    token.parse().map_err(|_| NfaParseError::UnknownSymbol {
+        at: Location::new(1, column),
+        symbol: token.to_owned(),
+    })
+}
+
 #[cfg(test)]
 mod test {
+    use crate::error::NfaParseError;
     use crate::row::Row;
 
     #[test]
@@ -73,10 +103,46 @@ mod test {
         r.unwrap();
     }
 
+    #[test]
+    fn test_non_numeric_id() {
+        let err = Row::from_str_custom("- 0 x a").unwrap_err();
+        match err {
+            NfaParseError::NonNumericId { at, token } => {
+                assert_eq!(at.column, 5);
+                assert_eq!(token, "x");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_bad_marker() {
+        let err = Row::from_str_custom("  * 0 1 a").unwrap_err();
+        match err {
+            NfaParseError::BadMarker { at, token } => {
+                assert_eq!(at.column, 3);
+                assert_eq!(token, "*");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_multi_char_symbol() {
+        let err = Row::from_str_custom("-  0 1 a bc").unwrap_err();
+        match err {
+            NfaParseError::UnknownSymbol { at, symbol } => {
+                assert_eq!(at.column, 10);
+                assert_eq!(symbol, "bc");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
     #[test]
     fn test_accept_str_parse_accepting() {
         let r = Row::from_str_custom("+ 0 1 a b c").unwrap();
-        assert_eq!(r.get_accepting_state(), true);
+        assert!(r.get_accepting_state());
         assert_eq!(r.get_from_id(), 0);
         assert_eq!(r.get_to_id(), 1);
         assert_eq!(*r.get_transitions(), vec!['a', 'b', 'c']);
@@ -85,7 +151,7 @@ mod test {
     #[test]
     fn test_accept_str_parse_non_accepting() {
         let r = Row::from_str_custom("- 0 1 a b c").unwrap();
-        assert_eq!(r.get_accepting_state(), false);
+        assert!(!r.get_accepting_state());
         assert_eq!(r.get_from_id(), 0);
         assert_eq!(r.get_to_id(), 1);
         assert_eq!(*r.get_transitions(), vec!['a', 'b', 'c']);
@@ -0,0 +1,186 @@
+//! Sets of NFA states for the subset construction.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+
+const BITS: usize = 64;
+
+/// A set of NFA states stored as a bitset. The last word is never zero,
+/// so equal sets have equal words and derived `Eq`, `Hash` and `Ord` agree
+/// with set equality.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StateSet {
+    words: Vec<u64>,
+}
+
+impl StateSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `state`, returning whether it was new.
+    pub fn insert(&mut self, state: usize) -> bool {
+        let (word, bit) = (state / BITS, 1 << (state % BITS));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let new = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        new
+    }
+
+    pub fn contains(&self, state: usize) -> bool {
+        self.words
+            .get(state / BITS)
+            .is_some_and(|word| word & (1 << (state % BITS)) != 0)
+    }
+
+    /// Adds every state of `other`.
+    pub fn union_with(&mut self, other: &StateSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// Whether the sets share a state.
+    pub fn intersects(&self, other: &StateSet) -> bool {
+        self.words.iter().zip(&other.words).any(|(a, b)| a & b != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The states in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * BITS + bit)
+            })
+        })
+    }
+}
+
+impl FromIterator<usize> for StateSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut set = StateSet::new();
+        for state in iter {
+            set.insert(state);
+        }
+        set
+    }
+}
+
+impl fmt::Debug for StateSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Gives every distinct set an id, storing each set once.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    sets: Vec<StateSet>,
+    // hash => ids of the sets with that hash
+    ids: HashMap<u64, Vec<usize>>,
+}
+
+impl Interner {
+    /// The id of `set`, and whether it was seen for the first time.
+    pub(crate) fn intern(&mut self, set: StateSet) -> (usize, bool) {
+        let mut hasher = DefaultHasher::new();
+        set.hash(&mut hasher);
+        let sets = &mut self.sets;
+        let ids = self.ids.entry(hasher.finish()).or_default();
+
+        if let Some(id) = ids.iter().find(|id| sets[**id] == set) {
+            return (*id, false);
+        }
+
+        let id = sets.len();
+        ids.push(id);
+        sets.push(set);
+        (id, true)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Forgets every set, so ids start from 0 again.
+    pub(crate) fn clear(&mut self) {
+        self.sets.clear();
+        self.ids.clear();
+    }
+
+    pub(crate) fn get(&self, id: usize) -> &StateSet {
+        &self.sets[id]
+    }
+
+    pub(crate) fn into_sets(self) -> Vec<StateSet> {
+        self.sets
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_operations() {
+        let mut a: StateSet = vec![3, 64, 200].into_iter().collect();
+        assert!(a.contains(64));
+        assert!(!a.contains(65));
+        assert!(!a.contains(1000));
+        assert!(!a.insert(3));
+        assert_eq!(a.len(), 3);
+
+        let b: StateSet = vec![1, 64].into_iter().collect();
+        assert!(a.intersects(&b));
+        a.union_with(&b);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![1, 3, 64, 200]);
+        assert_eq!(format!("{:?}", b), "{1, 64}");
+    }
+
+    #[test]
+    fn test_equal_sets_are_equal() {
+        let mut a = StateSet::new();
+        a.insert(1);
+        let mut b: StateSet = vec![1].into_iter().collect();
+        b.union_with(&StateSet::new());
+        assert_eq!(a, b);
+
+        let mut small: StateSet = vec![1].into_iter().collect();
+        small.union_with(&vec![300].into_iter().collect());
+        assert_ne!(small, a);
+        assert!(StateSet::new().is_empty());
+    }
+
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::default();
+        let a: StateSet = vec![0, 2].into_iter().collect();
+        let b: StateSet = vec![1].into_iter().collect();
+        assert_eq!(interner.intern(a.clone()), (0, true));
+        assert_eq!(interner.intern(b), (1, true));
+        assert_eq!(interner.intern(a.clone()), (0, false));
+        assert_eq!(interner.get(0), &a);
+        assert_eq!(interner.into_sets().len(), 2);
+    }
+}